@@ -0,0 +1,180 @@
+//! Exact (de)serialization of bitcoin amounts as bitcoind BTC values
+//!
+//! bitcoind represents amounts as BTC values with 8 decimals. Going through `f64` when
+//! building requests risks rounding errors, hence amounts are sent as strings (which
+//! bitcoind accepts wherever it expects an amount) formatted from the satoshi value.
+
+use bitcoin::{Amount, Denomination};
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+
+const SATS_PER_BTC: u64 = 100_000_000;
+
+/// An [`Amount`] serialized as an exact, 8 decimals BTC value.
+///
+/// When deserializing, both JSON strings and numbers are accepted. Numbers are parsed
+/// through their shortest decimal representation, which is exact for any amount
+/// bitcoind can return, and rejected if they have more than 8 decimals.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AmountBtc(pub Amount);
+
+impl From<Amount> for AmountBtc {
+    fn from(amount: Amount) -> Self {
+        Self(amount)
+    }
+}
+
+impl From<AmountBtc> for Amount {
+    fn from(amount: AmountBtc) -> Self {
+        amount.0
+    }
+}
+
+impl fmt::Display for AmountBtc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sats = self.0.to_sat();
+        write!(f, "{}.{:08}", sats / SATS_PER_BTC, sats % SATS_PER_BTC)
+    }
+}
+
+impl Serialize for AmountBtc {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for AmountBtc {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(AmountBtcVisitor)
+    }
+}
+
+struct AmountBtcVisitor;
+
+impl<'de> Visitor<'de> for AmountBtcVisitor {
+    type Value = AmountBtc;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a BTC amount as a number or a string")
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        // `Display` for f64 yields the shortest representation which round-trips,
+        // i.e. the 8 decimals string bitcoind serialized.
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Amount::from_str_in(v, Denomination::Bitcoin)
+            .map(AmountBtc)
+            .map_err(E::custom)
+    }
+}
+
+/// Serde helpers to use with `#[serde(with = "...")]` on [`Amount`] fields.
+pub mod as_btc {
+    use super::AmountBtc;
+    use bitcoin::Amount;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(amount: &Amount, serializer: S) -> Result<S::Ok, S::Error> {
+        AmountBtc(*amount).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Amount, D::Error> {
+        Ok(AmountBtc::deserialize(deserializer)?.0)
+    }
+
+    /// Same as [`as_btc`](super::as_btc) for `Option<Amount>` fields.
+    pub mod opt {
+        use super::AmountBtc;
+        use bitcoin::Amount;
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            amount: &Option<Amount>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            amount.map(AmountBtc).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Amount>, D::Error> {
+            Ok(Option::<AmountBtc>::deserialize(deserializer)?.map(|amount| amount.0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn serializes_with_8_decimals() {
+        let amount = AmountBtc(Amount::from_sat(123_456_789));
+
+        assert_eq!(serde_json::to_string(&amount).unwrap(), r#""1.23456789""#);
+        assert_eq!(
+            serde_json::to_string(&AmountBtc(Amount::from_sat(1))).unwrap(),
+            r#""0.00000001""#
+        );
+        assert_eq!(
+            serde_json::to_string(&AmountBtc(Amount::ZERO)).unwrap(),
+            r#""0.00000000""#
+        );
+    }
+
+    #[test]
+    fn deserializes_numbers_without_rounding_errors() {
+        let amount: AmountBtc = serde_json::from_str("0.30000001").unwrap();
+        assert_eq!(amount.0, Amount::from_sat(30_000_001));
+
+        let amount: AmountBtc = serde_json::from_str("20999999.99999999").unwrap();
+        assert_eq!(amount.0, Amount::from_sat(2_099_999_999_999_999));
+
+        let amount: AmountBtc = serde_json::from_str("3").unwrap();
+        assert_eq!(amount.0, Amount::from_btc(3.0).unwrap());
+    }
+
+    #[test]
+    fn deserializes_strings() {
+        let amount: AmountBtc = serde_json::from_str(r#""0.1""#).unwrap();
+
+        assert_eq!(amount.0, Amount::from_sat(10_000_000));
+    }
+
+    #[test]
+    fn rejects_sub_satoshi_precision() {
+        let res = serde_json::from_str::<AmountBtc>("0.000000001");
+
+        assert!(res.is_err());
+    }
+}
@@ -1,14 +1,27 @@
-use bitcoin::{Address, BlockHash, Transaction, Txid};
+use crate::amount::AmountBtc;
+use bitcoin::{Address, BlockHash, OutPoint, Transaction, Txid};
 use bitcoincore_rpc_json::{
-    FinalizePsbtResult, GetAddressInfoResult, GetBlockResult, GetBlockchainInfoResult,
-    GetDescriptorInfoResult, GetTransactionResult, GetWalletInfoResult, ListUnspentResultEntry,
-    LoadWalletResult, WalletCreateFundedPsbtResult,
+    EstimateMode, EstimateSmartFeeResult, FinalizePsbtResult, GetAddressInfoResult,
+    GetAddressInfoResultLabelPurpose, GetBlockResult, GetBlockTemplateOptions,
+    GetBlockTemplateResult, GetBlockchainInfoResult, GetDescriptorInfoResult, GetMiningInfoResult,
+    GetNetworkInfoResult, GetPeerInfoResult, GetTransactionResult, GetWalletInfoResult,
+    ImportMultiRescanSince, ImportMultiResult, ListBannedResult, ListReceivedByAddressResult,
+    ListSinceBlockResult, ListTransactionResult, ListUnspentResultEntry, LoadWalletResult,
+    SignRawTransactionResult, WalletCreateFundedPsbtResult,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::net::SocketAddr;
 
 #[jsonrpc_client::api]
 pub trait BitcoindRpcApi {
+    async fn addnode(&self, node: SocketAddr, command: AddNodeCommand) -> ();
+
+    async fn backupwallet(&self, destination: &std::path::Path) -> ();
+
+    async fn clearbanned(&self) -> ();
+
+    #[allow(clippy::too_many_arguments)]
     async fn createwallet(
         &self,
         wallet_name: &str,
@@ -16,16 +29,45 @@ pub trait BitcoindRpcApi {
         blank: Option<bool>,
         passphrase: Option<String>,
         avoid_reuse: Option<bool>,
+        descriptors: Option<bool>,
+        load_on_startup: Option<bool>,
     ) -> LoadWalletResult;
 
     async fn deriveaddresses(&self, descriptor: &str, range: Option<[u64; 2]>) -> Vec<Address>;
 
+    /// Disconnects the peer by address or, if `address` is `None`, by node id
+    async fn disconnectnode(&self, address: Option<SocketAddr>, nodeid: Option<u64>) -> ();
+
     async fn dumpwallet(&self, filename: &std::path::Path) -> DumpWalletResponse;
 
+    async fn encryptwallet(&self, passphrase: &str) -> String;
+
+    async fn estimaterawfee(&self, conf_target: u16) -> EstimateRawFeeResponse;
+
+    async fn estimatesmartfee(
+        &self,
+        conf_target: u16,
+        estimate_mode: Option<EstimateMode>,
+    ) -> EstimateSmartFeeResult;
+
     async fn finalizepsbt(&self, psbt: PsbtBase64) -> FinalizePsbtResult;
 
+    /// Mines a block paying to `output`, an address or a descriptor, containing exactly
+    /// `transactions`, given as txids of mempool transactions or raw transactions hex, in order.
+    async fn generateblock(&self, output: &str, transactions: Vec<String>)
+        -> GenerateBlockResponse;
+
+    async fn generatetodescriptor(
+        &self,
+        num_blocks: u32,
+        descriptor: &str,
+        maxtries: Option<u64>,
+    ) -> Vec<BlockHash>;
+
     async fn generatetoaddress(&self, nblocks: u32, address: Address) -> Vec<BlockHash>;
 
+    async fn getaddressesbylabel(&self, label: &str) -> HashMap<Address, AddressPurpose>;
+
     async fn getaddressinfo(&self, address: &Address) -> GetAddressInfoResult;
 
     async fn getbalance(
@@ -33,7 +75,7 @@ pub trait BitcoindRpcApi {
         minconf: Option<u32>,
         include_watchonly: Option<bool>,
         avoid_reuse: Option<bool>,
-    ) -> f64;
+    ) -> AmountBtc;
 
     async fn getblock(&self, blockhash: &bitcoin::BlockHash) -> GetBlockResult;
 
@@ -41,48 +83,319 @@ pub trait BitcoindRpcApi {
 
     async fn getblockcount(&self) -> u32;
 
+    async fn getblocktemplate(
+        &self,
+        template_request: GetBlockTemplateOptions,
+    ) -> GetBlockTemplateResult;
+
+    async fn getconnectioncount(&self) -> u64;
+
     async fn getdescriptorinfo(&self, descriptor: &str) -> GetDescriptorInfoResult;
 
+    async fn getmininginfo(&self) -> GetMiningInfoResult;
+
+    async fn getnetworkinfo(&self) -> GetNetworkInfoResult;
+
     async fn getnewaddress(&self, label: Option<String>, address_type: Option<String>) -> Address;
 
+    async fn getpeerinfo(&self) -> Vec<GetPeerInfoResult>;
+
+    async fn getrawmempool(&self) -> Vec<Txid>;
+
+    async fn getreceivedbyaddress(&self, address: &Address, minconf: Option<u32>) -> AmountBtc;
+
+    async fn getreceivedbylabel(&self, label: &str, minconf: Option<u32>) -> AmountBtc;
+
     async fn gettransaction(&self, txid: Txid) -> GetTransactionResult;
 
     async fn getwalletinfo(&self) -> GetWalletInfoResult;
 
+    async fn importdescriptors(
+        &self,
+        requests: Vec<ImportDescriptorsRequest>,
+    ) -> Vec<ImportMultiResult>;
+
     async fn joinpsbts(&self, txs: &[String]) -> PsbtBase64;
 
+    async fn listbanned(&self) -> Vec<ListBannedResult>;
+
+    /// The descriptors of the wallet, with their private keys if `private` is set, which is only
+    /// available from bitcoind 23.0. Only available on descriptor wallets from bitcoind 22.0.
+    async fn listdescriptors(&self, private: Option<bool>) -> ListDescriptorsResponse;
+
+    async fn listlabels(&self, purpose: Option<GetAddressInfoResultLabelPurpose>) -> Vec<String>;
+
+    async fn listlockunspent(&self) -> Vec<JsonOutPoint>;
+
+    async fn listreceivedbyaddress(
+        &self,
+        minconf: Option<u32>,
+        include_empty: Option<bool>,
+        include_watchonly: Option<bool>,
+        address_filter: Option<&Address>,
+    ) -> Vec<ListReceivedByAddressResult>;
+
+    async fn listsinceblock(
+        &self,
+        blockhash: Option<&BlockHash>,
+        target_confirmations: Option<u32>,
+        include_watchonly: Option<bool>,
+        include_removed: Option<bool>,
+    ) -> ListSinceBlockResult;
+
+    async fn listtransactions(
+        &self,
+        label: Option<&str>,
+        count: Option<u32>,
+        skip: Option<u32>,
+        include_watchonly: Option<bool>,
+    ) -> Vec<ListTransactionResult>;
+
     async fn listunspent(
         &self,
         minconf: Option<u32>,
         maxconf: Option<u32>,
         addresses: Option<Vec<Address>>,
         include_unsafe: Option<bool>,
+        query_options: Option<ListUnspentQueryOptions>,
     ) -> Vec<ListUnspentResultEntry>;
 
     async fn listwallets(&self) -> Vec<String>;
 
+    async fn loadwallet(&self, filename: &str, load_on_startup: Option<bool>) -> LoadWalletResult;
+
+    async fn lockunspent(&self, unlock: bool, transactions: Option<Vec<JsonOutPoint>>) -> bool;
+
+    /// Migrates the legacy wallet of the endpoint to a descriptor wallet, only available from
+    /// bitcoind 24.0
+    async fn migratewallet(&self) -> MigrateWalletResponse;
+
+    /// Requests a ping from all peers, the result is reported in `getpeerinfo`
+    async fn ping(&self) -> ();
+
+    /// Changes the fee of a mempool transaction by `fee_delta` satoshis when selecting
+    /// transactions for block templates, `dummy` must be `None`.
+    async fn prioritisetransaction(&self, txid: Txid, dummy: Option<u32>, fee_delta: i64) -> bool;
+
+    /// Only available from bitcoind 23.0
+    async fn restorewallet(
+        &self,
+        wallet_name: &str,
+        backup_file: &std::path::Path,
+        load_on_startup: Option<bool>,
+    ) -> LoadWalletResult;
+
     async fn sendrawtransaction(&self, hexstring: TransactionHex) -> String;
 
-    /// amount is btc
-    async fn sendtoaddress(&self, address: Address, amount: f64) -> String;
+    /// `dummy` must be empty, `fee_rate` is in sat/vB and only supported from bitcoind 0.21
+    async fn sendmany(
+        &self,
+        dummy: &str,
+        amounts: HashMap<String, AmountBtc>,
+        replaceable: Option<bool>,
+        fee_rate: Option<u64>,
+    ) -> String;
+
+    /// `fee_rate` is in sat/vB, it is only supported from bitcoind 0.21
+    async fn sendtoaddress(
+        &self,
+        address: Address,
+        amount: AmountBtc,
+        replaceable: Option<bool>,
+        fee_rate: Option<u64>,
+    ) -> String;
+
+    /// Bans (or unbans) `subnet`, an IP address with an optional netmask, for `bantime` seconds
+    /// or until `bantime` if `absolute` is set.
+    async fn setban(
+        &self,
+        subnet: &str,
+        command: SetBanCommand,
+        bantime: Option<u64>,
+        absolute: Option<bool>,
+    ) -> ();
 
     async fn sethdseed(&self, new_key_pool: Option<bool>, wif_private_key: Option<String>) -> ();
 
-    /// Outputs are {address, btc amount}
+    async fn setlabel(&self, address: &Address, label: &str) -> ();
+
+    /// Returns the new state of the network activity
+    async fn setnetworkactive(&self, state: bool) -> bool;
+
+    /// Returns the base64 encoded signature, only legacy addresses are supported
+    async fn signmessage(&self, address: &Address, message: &str) -> String;
+
+    async fn signmessagewithprivkey(&self, privkey: &str, message: &str) -> String;
+
+    async fn signrawtransactionwithwallet(
+        &self,
+        hexstring: TransactionHex,
+    ) -> SignRawTransactionResult;
+
+    /// Returns the reason of the rejection if the block is not accepted
+    async fn submitblock(&self, hexdata: String) -> Option<String>;
+
+    /// Fails with the reason of the rejection if the header is not accepted
+    async fn submitheader(&self, hexdata: String) -> ();
+
+    async fn unloadwallet(
+        &self,
+        wallet_name: Option<&str>,
+        load_on_startup: Option<bool>,
+    ) -> UnloadWalletResponse;
+
+    async fn verifymessage(&self, address: &Address, signature: &str, message: &str) -> bool;
+
+    /// Outputs are {address, amount}
     async fn walletcreatefundedpsbt(
         &self,
         inputs: &[bitcoincore_rpc_json::CreateRawTransactionInput],
-        outputs: HashMap<String, f64>,
+        outputs: HashMap<String, AmountBtc>,
     ) -> WalletCreateFundedPsbtResult;
 
+    async fn walletlock(&self) -> ();
+
+    async fn walletpassphrase(&self, passphrase: &str, timeout: u64) -> ();
+
+    async fn walletpassphrasechange(&self, oldpassphrase: &str, newpassphrase: &str) -> ();
+
     async fn walletprocesspsbt(&self, psbt: PsbtBase64) -> WalletProcessPsbtResponse;
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddNodeCommand {
+    /// Adds the node to the list of peers to keep connected to
+    Add,
+    /// Removes the node from the list of added peers, without disconnecting it
+    Remove,
+    /// Connects to the node once
+    Onetry,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SetBanCommand {
+    Add,
+    Remove,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct GenerateBlockResponse {
+    pub hash: BlockHash,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct EstimateRawFeeResponse {
+    pub short: Option<RawFeeEstimate>,
+    pub medium: Option<RawFeeEstimate>,
+    pub long: Option<RawFeeEstimate>,
+}
+
+/// The fee rate estimate of one of the time horizons tracked by bitcoind
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct RawFeeEstimate {
+    /// Fee rate per kvB, missing if there is not enough data
+    #[serde(default, with = "crate::amount::as_btc::opt")]
+    pub feerate: Option<bitcoin::Amount>,
+    pub scale: u32,
+    pub errors: Option<Vec<String>>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct DumpWalletResponse {
     pub filename: String,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct AddressPurpose {
+    pub purpose: GetAddressInfoResultLabelPurpose,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportDescriptorsRequest {
+    /// Descriptor with its checksum
+    pub desc: String,
+    pub timestamp: ImportMultiRescanSince,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<[u64; 2]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_index: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub internal: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ListDescriptorsResponse {
+    pub wallet_name: String,
+    pub descriptors: Vec<ListDescriptorsEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ListDescriptorsEntry {
+    /// Descriptor with its checksum
+    pub desc: String,
+    pub active: bool,
+    /// Only set for active descriptors
+    #[serde(default)]
+    pub internal: Option<bool>,
+}
+
+/// An outpoint as represented by bitcoind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct JsonOutPoint {
+    pub txid: Txid,
+    pub vout: u32,
+}
+
+impl From<OutPoint> for JsonOutPoint {
+    fn from(outpoint: OutPoint) -> Self {
+        Self {
+            txid: outpoint.txid,
+            vout: outpoint.vout,
+        }
+    }
+}
+
+impl From<JsonOutPoint> for OutPoint {
+    fn from(outpoint: JsonOutPoint) -> Self {
+        OutPoint::new(outpoint.txid, outpoint.vout)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListUnspentQueryOptions {
+    #[serde(
+        with = "crate::amount::as_btc::opt",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub minimum_amount: Option<bitcoin::Amount>,
+    #[serde(
+        with = "crate::amount::as_btc::opt",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub maximum_amount: Option<bitcoin::Amount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct MigrateWalletResponse {
+    pub wallet_name: String,
+    pub watchonly_name: Option<String>,
+    pub solvables_name: Option<String>,
+    pub backup_path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct UnloadWalletResponse {
+    #[serde(default)]
+    pub warning: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PsbtBase64(pub String);
 
@@ -0,0 +1,418 @@
+//! Assembly of custom blocks on top of `getblocktemplate`
+//!
+//! Unlike `generatetoaddress`, the blocks built here can have any coinbase, transactions in any
+//! order, arbitrary timestamps and version bits, which allows to test how the node handles
+//! invalid blocks and consensus edge cases. The proof of work is ground locally, which is
+//! instantaneous at the regtest difficulty.
+
+use crate::bitcoind_rpc::{self, Client};
+use crate::bitcoind_rpc_api::BitcoindRpcApi;
+use crate::{Error, Result};
+use bitcoin::blockdata::opcodes::all::{OP_PUSHBYTES_0, OP_RETURN};
+use bitcoin::blockdata::script::Builder;
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::hashes::Hash;
+use bitcoin::{
+    Address, Amount, Block, BlockHash, BlockHeader, Network, OutPoint, PackedLockTime, Script,
+    Sequence, Transaction, TxIn, TxMerkleNode, TxOut, Witness,
+};
+use bitcoincore_rpc_json::{
+    GetBlockTemplateModes, GetBlockTemplateOptions, GetBlockTemplateResult, GetBlockTemplateRules,
+};
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Prefix of the witness commitment output script: `OP_RETURN OP_PUSHBYTES_36 aa21a9ed`.
+const WITNESS_COMMITMENT_HEADER: [u8; 4] = [0xaa, 0x21, 0xa9, 0xed];
+
+/// Bits set in the version of blocks signalling with BIP 9.
+const VERSION_BITS_TOP_BITS: i32 = 0x2000_0000;
+
+/// Builds a block extending the tip of a block template.
+///
+/// By default, the block contains the transactions of the template and a coinbase paying the
+/// template reward to `reward_address`, with the time and version of the template.
+#[derive(Debug, Clone)]
+pub struct BlockBuilder {
+    template: GetBlockTemplateResult,
+    reward_address: Address,
+    coinbase: Option<Transaction>,
+    reward: Option<Amount>,
+    transactions: Option<Vec<Transaction>>,
+    time: Option<u32>,
+    version: Option<i32>,
+}
+
+impl BlockBuilder {
+    pub fn new(template: GetBlockTemplateResult, reward_address: Address) -> Self {
+        Self {
+            template,
+            reward_address,
+            coinbase: None,
+            reward: None,
+            transactions: None,
+            time: None,
+            version: None,
+        }
+    }
+
+    /// Fetch a template extending the tip of the node.
+    pub async fn from_node(client: &Client, reward_address: Address) -> Result<Self> {
+        client.validate_network(&reward_address).await?;
+
+        let mut rules = vec![GetBlockTemplateRules::SegWit];
+        if client.network().await? == Network::Signet {
+            rules.push(GetBlockTemplateRules::Signet);
+        }
+        let template = client
+            .getblocktemplate(GetBlockTemplateOptions {
+                mode: GetBlockTemplateModes::Template,
+                rules,
+                capabilities: vec![],
+            })
+            .await?;
+
+        Ok(Self::new(template, reward_address))
+    }
+
+    pub fn template(&self) -> &GetBlockTemplateResult {
+        &self.template
+    }
+
+    /// Use `coinbase` as is, the reward address, the reward and the witness commitment are
+    /// then ignored.
+    pub fn coinbase(mut self, coinbase: Transaction) -> Self {
+        self.coinbase = Some(coinbase);
+        self
+    }
+
+    /// Amount paid by the default coinbase.
+    ///
+    /// Defaults to the template reward, minus the fees of the template transactions left out
+    /// of the block.
+    pub fn reward(mut self, reward: Amount) -> Self {
+        self.reward = Some(reward);
+        self
+    }
+
+    /// Include `transactions`, in this order, instead of the template transactions.
+    pub fn transactions(mut self, transactions: Vec<Transaction>) -> Self {
+        self.transactions = Some(transactions);
+        self
+    }
+
+    pub fn time(mut self, time: u32) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    pub fn version(mut self, version: i32) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Signal for the BIP 9 deployment using `bit`, on top of the version of the template.
+    ///
+    /// # Panics
+    ///
+    /// If `bit` is not one of the 29 bits available to BIP 9 deployments.
+    pub fn version_bit(mut self, bit: u8) -> Self {
+        assert!(bit < 29, "BIP 9 deployments use bits 0 to 28");
+
+        let version = match self.version {
+            Some(version) => version,
+            None => i32::try_from(self.template.version).unwrap_or(VERSION_BITS_TOP_BITS),
+        };
+        self.version = Some(version | VERSION_BITS_TOP_BITS | (1 << bit));
+        self
+    }
+
+    /// Assemble the block, without solving its proof of work.
+    pub fn assemble(&self) -> Result<Block> {
+        let transactions = match &self.transactions {
+            Some(transactions) => transactions.clone(),
+            None => self
+                .template
+                .transactions
+                .iter()
+                .map(|tx| tx.transaction())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(bitcoind_rpc::Error::from)?,
+        };
+
+        let (coinbase, default_coinbase) = match &self.coinbase {
+            Some(coinbase) => (coinbase.clone(), false),
+            None => (self.default_coinbase(&transactions), true),
+        };
+
+        let bits = <[u8; 4]>::try_from(self.template.bits.as_slice())
+            .map(u32::from_be_bytes)
+            .map_err(|_| bitcoind_rpc::Error::UnexpectedResponse)?;
+        let version = match self.version {
+            Some(version) => version,
+            None => i32::try_from(self.template.version)
+                .map_err(|_| bitcoind_rpc::Error::UnexpectedResponse)?,
+        };
+        let time = match self.time {
+            Some(time) => time,
+            None => u32::try_from(self.template.current_time.max(self.template.min_time))
+                .map_err(|_| bitcoind_rpc::Error::UnexpectedResponse)?,
+        };
+
+        let mut block = Block {
+            header: BlockHeader {
+                version,
+                prev_blockhash: self.template.previous_block_hash,
+                merkle_root: TxMerkleNode::all_zeros(),
+                time,
+                bits,
+                nonce: 0,
+            },
+            txdata: std::iter::once(coinbase).chain(transactions).collect(),
+        };
+
+        if default_coinbase {
+            let witness_root = block.witness_root().expect("block has at least a coinbase");
+            let commitment = Block::compute_witness_commitment(&witness_root, &[0; 32]);
+            let mut commitment_data = WITNESS_COMMITMENT_HEADER.to_vec();
+            commitment_data.extend(commitment.as_ref());
+
+            block.txdata[0].output.push(TxOut {
+                value: 0,
+                script_pubkey: Builder::new()
+                    .push_opcode(OP_RETURN)
+                    .push_slice(&commitment_data)
+                    .into_script(),
+            });
+        }
+        block.header.merkle_root = block
+            .compute_merkle_root()
+            .expect("block has at least a coinbase");
+
+        Ok(block)
+    }
+
+    /// Assemble the block and grind its proof of work.
+    pub async fn build(&self) -> Result<Block> {
+        let block = self.assemble()?;
+
+        Ok(tokio::task::spawn_blocking(move || grind(block))
+            .await
+            .expect("grinding does not panic"))
+    }
+
+    /// Build the block and submit it to the node.
+    pub async fn submit(&self, client: &Client) -> Result<BlockHash> {
+        let block = self.build().await?;
+
+        submit_block(client, &block).await
+    }
+
+    /// The coinbase of the block, without its witness commitment output.
+    fn default_coinbase(&self, transactions: &[Transaction]) -> Transaction {
+        let reward = match self.reward {
+            Some(reward) => reward,
+            None => {
+                let included_fees = self
+                    .template
+                    .transactions
+                    .iter()
+                    .filter(|template_tx| {
+                        transactions.iter().any(|tx| tx.txid() == template_tx.txid)
+                    })
+                    .map(|template_tx| template_tx.fee)
+                    .sum::<Amount>();
+                let template_fees = self
+                    .template
+                    .transactions
+                    .iter()
+                    .map(|template_tx| template_tx.fee)
+                    .sum::<Amount>();
+
+                self.template.coinbase_value - template_fees + included_fees
+            }
+        };
+
+        let height = u32::try_from(self.template.height).expect("height fits in u32");
+        let mut coinbase = coinbase(height, reward, self.reward_address.script_pubkey());
+        // Witness reserved value
+        coinbase.input[0].witness = Witness::from_vec(vec![vec![0; 32]]);
+
+        coinbase
+    }
+}
+
+/// Submit `block` to the node, returning the reason of the rejection as
+/// [`Error::BlockRejected`].
+pub async fn submit_block(client: &Client, block: &Block) -> Result<BlockHash> {
+    if let Some(reason) = client.submitblock(serialize_hex(block)).await? {
+        return Err(Error::BlockRejected(reason));
+    }
+
+    Ok(block.block_hash())
+}
+
+/// Increments the nonce, and the time once all nonces are exhausted, until the block hash
+/// meets the target. The search is split across all available cores.
+///
+/// This is CPU bound and should be run on a blocking thread.
+pub fn grind(mut block: Block) -> Block {
+    loop {
+        match grind_nonce(&block.header) {
+            Some(nonce) => {
+                block.header.nonce = nonce;
+                return block;
+            }
+            None => block.header.time += 1,
+        }
+    }
+}
+
+/// The nonce for which the hash of `header` meets its target, `None` if there is none at the
+/// time of the header. The search is split across all available cores.
+///
+/// This is CPU bound and should be run on a blocking thread.
+pub fn grind_nonce(header: &BlockHeader) -> Option<u32> {
+    let threads = std::thread::available_parallelism()
+        .ok()
+        .and_then(|threads| u32::try_from(threads.get()).ok())
+        .unwrap_or(1);
+    let target = header.target();
+    let found = AtomicBool::new(false);
+    let nonce = Mutex::new(None);
+
+    std::thread::scope(|scope| {
+        for first_nonce in 0..threads {
+            let (found, nonce, target) = (&found, &nonce, &target);
+            let mut header = *header;
+
+            scope.spawn(move || {
+                header.nonce = first_nonce;
+                while !found.load(Ordering::Relaxed) {
+                    if header.validate_pow(target).is_ok() {
+                        found.store(true, Ordering::Relaxed);
+                        *nonce.lock().expect("lock is not poisoned") = Some(header.nonce);
+                        return;
+                    }
+                    header.nonce = match header.nonce.checked_add(threads) {
+                        Some(next_nonce) => next_nonce,
+                        None => return,
+                    };
+                }
+            });
+        }
+    });
+
+    nonce.into_inner().expect("lock is not poisoned")
+}
+
+/// A coinbase paying `value` to `script_pubkey` at `height`, without witness commitment.
+///
+/// Its script starts with the BIP 34 height, followed by `OP_0` as it must be at least 2 bytes
+/// long.
+pub fn coinbase(height: u32, value: Amount, script_pubkey: Script) -> Transaction {
+    Transaction {
+        version: 1,
+        lock_time: PackedLockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: Builder::new()
+                .push_int(i64::from(height))
+                .push_opcode(OP_PUSHBYTES_0)
+                .into_script(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: value.to_sat(),
+            script_pubkey,
+        }],
+    }
+}
+
+#[cfg(all(test, feature = "test-docker"))]
+mod test {
+    use super::*;
+    use crate::Bitcoind;
+
+    async fn funded_node(bitcoind: &Bitcoind<'_>) -> (Client, Address) {
+        let client = Client::new(bitcoind.node_url.clone());
+        client
+            .createwallet(&bitcoind.wallet_name, None, None, None, None, None, None)
+            .await
+            .unwrap();
+        let address = client
+            .with_wallet(&bitcoind.wallet_name)
+            .unwrap()
+            .getnewaddress(None, None)
+            .await
+            .unwrap();
+        // Two mature coinbases, to fund independent transactions
+        client
+            .generatetoaddress(102, address.clone())
+            .await
+            .unwrap();
+
+        (client, address)
+    }
+
+    #[tokio::test]
+    async fn submit_block_with_custom_fields() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind = Bitcoind::new(&tc_client).unwrap();
+        let (client, address) = funded_node(&bitcoind).await;
+
+        let amount = Amount::from_btc(1.0).unwrap();
+        let wallet = client.with_wallet(&bitcoind.wallet_name).unwrap();
+        let mut transactions = Vec::new();
+        for _ in 0..2 {
+            let txid = client
+                .send_to_address(&bitcoind.wallet_name, address.clone(), amount)
+                .await
+                .unwrap();
+            let transaction = client.get_raw_transaction(txid).await.unwrap();
+
+            // The next transaction spends the other coinbase rather than this change
+            let outpoints = (0..transaction.output.len())
+                .map(|vout| OutPoint::new(txid, u32::try_from(vout).unwrap()).into())
+                .collect();
+            wallet.lockunspent(false, Some(outpoints)).await.unwrap();
+            transactions.push(transaction);
+        }
+        // Independent transactions can be included in any order
+        transactions.reverse();
+
+        let builder = BlockBuilder::from_node(&client, address).await.unwrap();
+        let min_time = u32::try_from(builder.template().min_time).unwrap();
+        let block_hash = builder
+            .transactions(transactions)
+            .time(min_time)
+            .version_bit(3)
+            .submit(&client)
+            .await
+            .unwrap();
+
+        let block = client.getblock(&block_hash).await.unwrap();
+        assert_eq!(block.height, 103);
+        assert_eq!(block.time, usize::try_from(min_time).unwrap());
+        assert!(client.getrawmempool().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reject_invalid_blocks() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind = Bitcoind::new(&tc_client).unwrap();
+        let (client, address) = funded_node(&bitcoind).await;
+
+        let builder = BlockBuilder::from_node(&client, address).await.unwrap();
+        let min_time = u32::try_from(builder.template().min_time).unwrap();
+
+        let res = builder.clone().time(min_time - 1).submit(&client).await;
+        assert!(matches!(res, Err(Error::BlockRejected(reason)) if reason == "time-too-old"));
+
+        let reward = builder.template().coinbase_value + Amount::from_sat(1);
+        let res = builder.reward(reward).submit(&client).await;
+        assert!(matches!(res, Err(Error::BlockRejected(reason)) if reason == "bad-cb-amount"));
+    }
+}
@@ -0,0 +1,336 @@
+//! Blocking wrappers of [`crate::Client`] and [`crate::Wallet`], for tests which do not run
+//! in an async runtime
+//!
+//! Each call runs the async version on a current thread tokio runtime owned by the wrapper and
+//! blocks until it completes. The wrappers must hence not be used, nor dropped, from within an
+//! async context: tokio panics when a runtime is started, or dropped, inside another one.
+//!
+//! ```rust
+//! use bitcoin_harness::{blocking, Bitcoind, BitcoindRpcApi};
+//!
+//! let tc_client = testcontainers::clients::Cli::default();
+//! let bitcoind = Bitcoind::new(&tc_client).unwrap();
+//! let wallet = blocking::Wallet::new("my_wallet", bitcoind.node_url.clone()).unwrap();
+//! let address = wallet.new_address().unwrap();
+//!
+//! let client = blocking::Client::new(bitcoind.node_url.clone()).unwrap();
+//! client
+//!     .run(|client| client.generatetoaddress(101, address))
+//!     .unwrap();
+//!
+//! assert_eq!(wallet.balance().unwrap(), bitcoin::Amount::from_btc(50.0).unwrap());
+//! ```
+
+use crate::bitcoind_rpc::{Error, Result};
+use crate::bitcoind_rpc_api::{MigrateWalletResponse, PsbtBase64, WalletProcessPsbtResponse};
+use crate::wallet::{ListUnspentQuery, SendOptions};
+use bitcoin::util::misc::MessageSignature;
+use bitcoin::{Address, Amount, BlockHash, Network, OutPoint, PrivateKey, Transaction, Txid};
+use bitcoincore_rpc_json::{
+    CreateRawTransactionInput, FinalizePsbtResult, GetAddressInfoResult, GetRawTransactionResult,
+    GetTransactionResult, GetWalletInfoResult, ListReceivedByAddressResult, ListSinceBlockResult,
+    ListTransactionResult, ListUnspentResultEntry,
+};
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use url::Url;
+
+/// Define blocking methods calling the async method of the same name of `self.inner`.
+macro_rules! blocking_methods {
+    ($target:literal; $(fn $name:ident(&self $(, $arg:ident: $ty:ty)* $(,)?) -> $ret:ty;)*) => {
+        $(
+            #[doc = concat!("Blocking version of [`", $target, "::", stringify!($name), "`].")]
+            pub fn $name(&self $(, $arg: $ty)*) -> Result<$ret> {
+                self.runtime.block_on(self.inner.$name($($arg),*))
+            }
+        )*
+    };
+}
+
+/// A blocking bitcoind RPC client.
+#[derive(Debug, Clone)]
+pub struct Client {
+    inner: crate::Client,
+    runtime: Arc<Runtime>,
+}
+
+impl Client {
+    pub fn new(url: Url) -> Result<Self> {
+        Ok(Self {
+            inner: crate::Client::new(url),
+            runtime: Arc::new(runtime()?),
+        })
+    }
+
+    /// Wrap an async client, built with [`crate::Client::builder`] for instance.
+    pub fn from_async(client: crate::Client) -> Result<Self> {
+        Ok(Self {
+            inner: client,
+            runtime: Arc::new(runtime()?),
+        })
+    }
+
+    /// A client to the wallet `wallet_name`, sharing the runtime of this client.
+    pub fn with_wallet(&self, wallet_name: &str) -> Result<Self> {
+        Ok(Self {
+            inner: self.inner.with_wallet(wallet_name)?,
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// Block on a call to the async client, to reach the RPCs of
+    /// [`BitcoindRpcApi`](crate::BitcoindRpcApi) for instance:
+    /// `client.run(|client| client.getblockcount())`.
+    pub fn run<'a, F, T>(&'a self, call: impl FnOnce(&'a crate::Client) -> F) -> T
+    where
+        F: Future<Output = T>,
+    {
+        self.runtime.block_on(call(&self.inner))
+    }
+
+    blocking_methods! {
+        "crate::Client";
+        fn network(&self) -> Network;
+        fn validate_network(&self, address: &Address) -> ();
+        fn median_time(&self) -> u64;
+        fn set_hd_seed(
+            &self,
+            wallet_name: &str,
+            new_key_pool: Option<bool>,
+            wif_private_key: Option<String>,
+        ) -> ();
+        fn send_to_address(&self, wallet_name: &str, address: Address, amount: Amount) -> Txid;
+        fn get_raw_transaction(&self, txid: Txid) -> Transaction;
+        fn get_raw_transaction_verbose(&self, txid: Txid) -> GetRawTransactionResult;
+        fn fund_psbt(
+            &self,
+            wallet_name: &str,
+            inputs: &[CreateRawTransactionInput],
+            address: Address,
+            amount: Amount,
+        ) -> String;
+        fn join_psbts(&self, wallet_name: &str, psbts: &[String]) -> PsbtBase64;
+        fn wallet_process_psbt(
+            &self,
+            wallet_name: &str,
+            psbt: PsbtBase64,
+        ) -> WalletProcessPsbtResponse;
+        fn finalize_psbt(&self, wallet_name: &str, psbt: PsbtBase64) -> FinalizePsbtResult;
+        fn address_info(&self, wallet_name: &str, address: &Address) -> GetAddressInfoResult;
+        fn generate_block(&self, address: &Address, txids: &[Txid]) -> BlockHash;
+        fn sign_message(
+            &self,
+            wallet_name: &str,
+            address: &Address,
+            message: &str,
+        ) -> MessageSignature;
+        fn sign_message_with_privkey(
+            &self,
+            private_key: &PrivateKey,
+            message: &str,
+        ) -> MessageSignature;
+        fn verify_message(
+            &self,
+            address: &Address,
+            signature: &MessageSignature,
+            message: &str,
+        ) -> bool;
+    }
+}
+
+/// A blocking wrapper to bitcoind wallet
+#[derive(Debug)]
+pub struct Wallet {
+    inner: crate::Wallet,
+    runtime: Arc<Runtime>,
+}
+
+impl Wallet {
+    /// Create a wallet on the bitcoind instance or use the wallet with the same name
+    /// if it exists.
+    pub fn new(name: &str, url: Url) -> Result<Self> {
+        let runtime = runtime()?;
+        let inner = runtime.block_on(crate::Wallet::new(name, url))?;
+
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// Create a wallet encrypted with `passphrase` on the bitcoind instance or use the
+    /// wallet with the same name if it exists. The wallet is locked once created.
+    pub fn new_encrypted(name: &str, url: Url, passphrase: &str) -> Result<Self> {
+        let runtime = runtime()?;
+        let inner = runtime.block_on(crate::Wallet::new_encrypted(name, url, passphrase))?;
+
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// Create a wallet, or use the existing one, through `client`.
+    pub fn with_client(name: &str, client: Client) -> Result<Self> {
+        let inner = client
+            .runtime
+            .block_on(crate::Wallet::with_client(name, client.inner))?;
+
+        Ok(Self {
+            inner,
+            runtime: client.runtime,
+        })
+    }
+
+    /// Restore a wallet named `name` from a backup file on the bitcoind instance.
+    pub fn restore(name: &str, url: Url, backup_file: &Path) -> Result<Self> {
+        let runtime = runtime()?;
+        let inner = runtime.block_on(crate::Wallet::restore(name, url, backup_file))?;
+
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    /// A blocking client to the node of the wallet, sharing the runtime of the wallet.
+    pub fn client(&self) -> Client {
+        Client {
+            inner: self.inner.client.clone(),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// Unlock the wallet for `timeout`, it is locked again when the returned guard is dropped.
+    pub fn unlock(&self, passphrase: &str, timeout: Duration) -> Result<UnlockGuard> {
+        let guard = self
+            .runtime
+            .block_on(self.inner.unlock(passphrase, timeout))?;
+
+        Ok(UnlockGuard {
+            guard: Some(guard),
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    blocking_methods! {
+        "crate::Wallet";
+        fn info(&self) -> GetWalletInfoResult;
+        fn encrypt(&self, passphrase: &str) -> ();
+        fn lock(&self) -> ();
+        fn change_passphrase(&self, old_passphrase: &str, new_passphrase: &str) -> ();
+        fn backup(&self, destination: &Path) -> ();
+        fn unload(&self) -> ();
+        fn load(&self) -> ();
+        fn migrate(&self) -> MigrateWalletResponse;
+        fn median_time(&self) -> u64;
+        fn new_address(&self) -> Address;
+        fn new_address_with_label(&self, label: &str) -> Address;
+        fn set_label(&self, address: &Address, label: &str) -> ();
+        fn addresses_by_label(&self, label: &str) -> Vec<Address>;
+        fn labels(&self) -> Vec<String>;
+        fn received_by_address(&self, address: &Address) -> Amount;
+        fn received_by_label(&self, label: &str) -> Amount;
+        fn list_received_by_address(&self) -> Vec<ListReceivedByAddressResult>;
+        fn balance(&self) -> Amount;
+        fn send_to_address(&self, address: Address, amount: Amount) -> Txid;
+        fn send_to_address_with(
+            &self,
+            address: Address,
+            amount: Amount,
+            options: SendOptions,
+        ) -> Txid;
+        fn send_raw_transaction(&self, transaction: Transaction) -> Txid;
+        fn double_spend(&self, txid: Txid, destination: &Address, fee_rate: u64) -> Txid;
+        fn build_double_spend(
+            &self,
+            original: &Transaction,
+            destination: &Address,
+            fee_rate: u64,
+        ) -> Transaction;
+        fn sign_raw_transaction(&self, transaction: Transaction) -> Transaction;
+        fn get_raw_transaction(&self, txid: Txid) -> Transaction;
+        fn get_wallet_transaction(&self, txid: Txid) -> GetTransactionResult;
+        fn transactions(&self, count: u32, skip: u32) -> Vec<ListTransactionResult>;
+        fn since_block(&self, block_hash: Option<BlockHash>) -> ListSinceBlockResult;
+        fn address_info(&self, address: &Address) -> GetAddressInfoResult;
+        fn list_unspent(&self) -> Vec<ListUnspentResultEntry>;
+        fn list_unspent_with(&self, query: ListUnspentQuery) -> Vec<ListUnspentResultEntry>;
+        fn lock_unspent(&self, outpoints: &[OutPoint]) -> ();
+        fn unlock_unspent(&self, outpoints: &[OutPoint]) -> ();
+        fn list_locked(&self) -> Vec<OutPoint>;
+        fn sign_message(&self, address: &Address, message: &str) -> MessageSignature;
+        fn fund_psbt(&self, address: Address, amount: Amount) -> String;
+        fn join_psbts(&self, psbts: &[String]) -> PsbtBase64;
+        fn wallet_process_psbt(&self, psbt: PsbtBase64) -> WalletProcessPsbtResponse;
+        fn finalize_psbt(&self, psbt: PsbtBase64) -> FinalizePsbtResult;
+        fn transaction_block_height(&self, txid: Txid) -> Option<u32>;
+    }
+}
+
+/// Keeps a wallet unlocked, see [`Wallet::unlock`]. The wallet is locked again, blocking, when
+/// the guard is dropped.
+#[derive(Debug)]
+#[must_use = "the wallet is locked again when the guard is dropped"]
+pub struct UnlockGuard {
+    guard: Option<crate::wallet::UnlockGuard>,
+    runtime: Arc<Runtime>,
+}
+
+impl UnlockGuard {
+    pub fn lock(mut self) -> Result<()> {
+        match self.guard.take() {
+            Some(guard) => self.runtime.block_on(guard.lock()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for UnlockGuard {
+    fn drop(&mut self) {
+        if let Some(guard) = self.guard.take() {
+            if let Err(error) = self.runtime.block_on(guard.lock()) {
+                tracing::warn!(%error, "failed to lock the wallet");
+            }
+        }
+    }
+}
+
+fn runtime() -> Result<Runtime> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(Error::Runtime)
+}
+
+#[cfg(all(test, feature = "test-docker"))]
+mod test {
+    use super::*;
+    use crate::{Bitcoind, BitcoindRpcApi};
+
+    #[test]
+    fn fund_wallet_without_async_runtime() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind = Bitcoind::new(&tc_client).unwrap();
+        let wallet = Wallet::new("wallet", bitcoind.node_url.clone()).unwrap();
+        let address = wallet.new_address().unwrap();
+
+        wallet
+            .client()
+            .run(|client| client.generatetoaddress(101, address.clone()))
+            .unwrap();
+        assert_eq!(wallet.balance().unwrap(), Amount::from_btc(50.0).unwrap());
+
+        let txid = wallet
+            .send_to_address(address, Amount::from_btc(1.0).unwrap())
+            .unwrap();
+        assert!(wallet.get_wallet_transaction(txid).is_ok());
+    }
+}
@@ -0,0 +1,209 @@
+//! Features of the Bitcoin Core version a node runs
+//!
+//! The version is detected by [`Client::capabilities`](crate::Client::capabilities), tests can
+//! query it to skip the cases the node does not support.
+
+/// Versions as reported in the `version` field of `getnetworkinfo`
+pub(crate) const V0_19: u32 = 190_000;
+pub(crate) const V0_21: u32 = 210_000;
+pub(crate) const V22: u32 = 220_000;
+pub(crate) const V23: u32 = 230_000;
+pub(crate) const V24: u32 = 240_000;
+pub(crate) const V25: u32 = 250_000;
+pub(crate) const V28: u32 = 280_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The `version` field of `getnetworkinfo`, e.g. 210000 for 0.21.0 or 240100 for 24.1
+    pub version: u32,
+    /// Descriptor wallets, from 0.21
+    pub descriptors: bool,
+    /// `createwallet` creates descriptor wallets unless told otherwise, from 23.0
+    pub descriptors_by_default: bool,
+    /// Taproot descriptors and bech32m addresses in the wallet, from 22.0
+    pub taproot: bool,
+    /// The `-mempoolfullrbf` option, from 24.0. It is enabled by default from 28.0.
+    pub mempool_full_rbf: bool,
+    /// Relay of packages of one parent and one child, from 28.0
+    pub package_relay: bool,
+}
+
+impl Capabilities {
+    pub fn from_version(version: u32) -> Self {
+        Self {
+            version,
+            descriptors: version >= V0_21,
+            descriptors_by_default: version >= V23,
+            taproot: version >= V22,
+            mempool_full_rbf: version >= V24,
+            package_relay: version >= V28,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "mock")]
+    use crate::bitcoind_rpc::{CreateWalletOptions, Error};
+    #[cfg(feature = "mock")]
+    use crate::mock::MockBitcoind;
+    #[cfg(feature = "mock")]
+    use crate::{Client, Wallet};
+
+    #[cfg(feature = "mock")]
+    fn network_info(version: u32) -> serde_json::Value {
+        serde_json::json!({
+            "version": version,
+            "subversion": "/Satoshi/",
+            "protocolversion": 70015,
+            "localservices": "0000000000000409",
+            "localrelay": true,
+            "timeoffset": 0,
+            "connections": 0,
+            "networkactive": true,
+            "networks": [],
+            "relayfee": 0.00001,
+            "incrementalfee": 0.00001,
+            "localaddresses": [],
+            "warnings": "",
+        })
+    }
+
+    #[test]
+    fn capabilities_of_versions() {
+        let v0_21 = Capabilities::from_version(210_000);
+        assert!(v0_21.descriptors);
+        assert!(!v0_21.descriptors_by_default);
+        assert!(!v0_21.taproot);
+
+        let v24_1 = Capabilities::from_version(240_100);
+        assert!(v24_1.descriptors_by_default);
+        assert!(v24_1.taproot);
+        assert!(v24_1.mempool_full_rbf);
+        assert!(!v24_1.package_relay);
+
+        assert!(Capabilities::from_version(280_000).package_relay);
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn reject_options_unknown_to_the_node() {
+        let bitcoind = MockBitcoind::new().unwrap();
+        bitcoind.respond("getnetworkinfo", network_info(200_000));
+        let client = Client::new(bitcoind.node_url.clone());
+
+        assert_eq!(client.capabilities().await.unwrap().version, 200_000);
+        let res = client
+            .create_wallet(
+                "descriptors",
+                CreateWalletOptions {
+                    descriptors: Some(true),
+                    ..CreateWalletOptions::default()
+                },
+            )
+            .await;
+        assert!(matches!(res, Err(Error::Unsupported { .. })));
+
+        client
+            .create_wallet("legacy", CreateWalletOptions::default())
+            .await
+            .unwrap();
+        let createwallet = bitcoind
+            .calls()
+            .into_iter()
+            .find(|call| call.method == "createwallet")
+            .unwrap();
+        assert_eq!(
+            createwallet.params,
+            serde_json::json!({ "wallet_name": "legacy" })
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn reject_wallet_options_of_0_19() {
+        let bitcoind = MockBitcoind::new().unwrap();
+        bitcoind.respond("getnetworkinfo", network_info(180_100));
+        let client = Client::new(bitcoind.node_url.clone());
+
+        let encrypted = client
+            .create_wallet(
+                "encrypted",
+                CreateWalletOptions {
+                    passphrase: Some("passphrase".to_string()),
+                    ..CreateWalletOptions::default()
+                },
+            )
+            .await;
+        let avoid_reuse = client
+            .create_wallet(
+                "avoid_reuse",
+                CreateWalletOptions {
+                    avoid_reuse: Some(true),
+                    ..CreateWalletOptions::default()
+                },
+            )
+            .await;
+
+        assert!(
+            matches!(encrypted, Err(Error::Unsupported { feature, version: 180_100 }) if feature.contains("encryption"))
+        );
+        assert!(matches!(
+            avoid_reuse,
+            Err(Error::Unsupported {
+                feature: "avoid_reuse",
+                version: 180_100
+            })
+        ));
+        assert!(!bitcoind
+            .calls()
+            .iter()
+            .any(|call| call.method == "createwallet"));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn reject_hd_seed_of_descriptor_wallet() {
+        let bitcoind = MockBitcoind::new().unwrap();
+        bitcoind.respond("getnetworkinfo", network_info(230_000));
+        bitcoind.respond(
+            "getwalletinfo",
+            serde_json::json!({ "walletname": "wallet", "descriptors": true }),
+        );
+        let client = Client::new(bitcoind.node_url.clone());
+
+        let res = client.set_hd_seed("wallet", None, None).await;
+
+        assert!(matches!(res, Err(Error::Unsupported { version: V23, .. })));
+        assert!(!bitcoind
+            .calls()
+            .iter()
+            .any(|call| call.method == "sethdseed"));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn reject_rpcs_unknown_to_the_node() {
+        let bitcoind = MockBitcoind::new().unwrap();
+        let wallet = Wallet::new("wallet", bitcoind.node_url.clone())
+            .await
+            .unwrap();
+
+        let restored = Wallet::restore(
+            "restored",
+            bitcoind.node_url.clone(),
+            std::path::Path::new("/tmp/wallet.bak"),
+        )
+        .await;
+        assert!(matches!(restored, Err(Error::Unsupported { version, .. }) if version == V0_21));
+        assert!(matches!(
+            wallet.migrate().await,
+            Err(Error::Unsupported { .. })
+        ));
+        assert!(!bitcoind
+            .calls()
+            .iter()
+            .any(|call| call.method == "restorewallet" || call.method == "migratewallet"));
+    }
+}
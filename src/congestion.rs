@@ -0,0 +1,262 @@
+//! Mempool congestion, to test protocols when blocks are full
+//!
+//! Dedicated spam wallets are funded with many small outputs so that they can broadcast a large
+//! number of transactions without chaining unconfirmed ones. Combined with a node started with
+//! [`BitcoindArgs::block_max_weight`](crate::image::BitcoindArgs::block_max_weight), only the
+//! transactions paying the highest fee rates are mined and low fee transactions stay
+//! unconfirmed.
+
+use crate::bitcoind_rpc::Client;
+use crate::bitcoind_rpc_api::BitcoindRpcApi;
+use crate::fee_market::FeeProfile;
+use crate::{Bitcoind, Error, Result, Wallet};
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::secp256k1::rand::{thread_rng, Rng};
+use bitcoin::{Address, Amount, Txid};
+use std::collections::HashMap;
+
+/// Value of each of the outputs funding the spam wallets
+const FUNDING_OUTPUT_VALUE: Amount = Amount::from_sat(1_000_000);
+
+/// Value of each of the outputs of the spam transactions
+const SPAM_OUTPUT_VALUE: Amount = Amount::from_sat(10_000);
+
+/// How the fee rates of the spam transactions are picked, in sat/vB.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeeRateDistribution {
+    Fixed(u64),
+    /// Uniformly random between `min` and `max`, inclusive
+    Uniform {
+        min: u64,
+        max: u64,
+    },
+    /// Cycle through the given fee rates
+    Cycle(Vec<u64>),
+}
+
+impl FeeRateDistribution {
+    /// Fails with [`Error::InvalidFeeRates`] if no fee rate can be picked, from an empty cycle
+    /// or a uniform range whose `min` is above its `max`.
+    pub fn validate(&self) -> Result<()> {
+        let valid = match self {
+            FeeRateDistribution::Fixed(_) => true,
+            FeeRateDistribution::Uniform { min, max } => min <= max,
+            FeeRateDistribution::Cycle(fee_rates) => !fee_rates.is_empty(),
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(Error::InvalidFeeRates(self.clone()))
+        }
+    }
+
+    /// Must only be called on a valid distribution.
+    fn fee_rate(&self, index: usize) -> u64 {
+        match self {
+            FeeRateDistribution::Fixed(fee_rate) => *fee_rate,
+            FeeRateDistribution::Uniform { min, max } => thread_rng().gen_range(*min..=*max),
+            FeeRateDistribution::Cycle(fee_rates) => fee_rates[index % fee_rates.len()],
+        }
+    }
+}
+
+impl From<FeeProfile> for FeeRateDistribution {
+    fn from(profile: FeeProfile) -> Self {
+        FeeRateDistribution::Cycle(profile.fee_rates().to_vec())
+    }
+}
+
+/// Shape of the spam transactions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spam {
+    /// Number of transactions to broadcast
+    pub transactions: usize,
+    /// Number of outputs of each transaction, which sets their size
+    pub outputs: usize,
+    pub fee_rates: FeeRateDistribution,
+}
+
+/// Spam wallets flooding the mempool of a node.
+#[derive(Debug)]
+pub struct Congestion {
+    wallets: Vec<SpamWallet>,
+}
+
+#[derive(Debug)]
+struct SpamWallet {
+    wallet: Wallet,
+    addresses: Vec<Address>,
+}
+
+impl Congestion {
+    /// Create `nwallets` spam wallets with `outputs_per_wallet` confirmed outputs each, funded
+    /// by the test wallet of `bitcoind`.
+    ///
+    /// Each spam transaction spends one of these outputs, a wallet can hence broadcast about
+    /// `outputs_per_wallet` transactions before having to chain unconfirmed ones.
+    pub async fn new(
+        bitcoind: &Bitcoind<'_>,
+        nwallets: usize,
+        outputs_per_wallet: usize,
+    ) -> Result<Self> {
+        let client = Client::new(bitcoind.node_url.clone());
+
+        let mut wallets = Vec::with_capacity(nwallets);
+        let mut funding_outputs = HashMap::new();
+        for index in 0..nwallets {
+            let wallet = Wallet::new(&format!("spam_{}", index), bitcoind.node_url.clone()).await?;
+            for _ in 0..outputs_per_wallet {
+                let address = wallet.new_address().await?;
+                funding_outputs.insert(address.to_string(), FUNDING_OUTPUT_VALUE.into());
+            }
+            wallets.push(SpamWallet {
+                wallet,
+                addresses: Vec::new(),
+            });
+        }
+
+        client
+            .with_wallet(&bitcoind.wallet_name)?
+            .sendmany("", funding_outputs, None, None)
+            .await?;
+        let reward_address = client
+            .with_wallet(&bitcoind.wallet_name)?
+            .getnewaddress(None, None)
+            .await?;
+        bitcoind.generate(1, reward_address).await?;
+
+        Ok(Self { wallets })
+    }
+
+    pub fn wallets(&self) -> impl Iterator<Item = &Wallet> {
+        self.wallets.iter().map(|spam_wallet| &spam_wallet.wallet)
+    }
+
+    /// Broadcast the `spam` transactions, spread across the spam wallets. Returns their txids.
+    ///
+    /// Fails with [`Error::InvalidFeeRates`] before broadcasting anything if the fee rate
+    /// distribution of `spam` is invalid.
+    pub async fn flood(&mut self, spam: &Spam) -> Result<Vec<Txid>> {
+        spam.fee_rates.validate()?;
+
+        for spam_wallet in self.wallets.iter_mut() {
+            while spam_wallet.addresses.len() < spam.outputs {
+                spam_wallet
+                    .addresses
+                    .push(spam_wallet.wallet.new_address().await?);
+            }
+        }
+
+        let mut txids = Vec::with_capacity(spam.transactions);
+        for (index, spam_wallet) in self
+            .wallets
+            .iter()
+            .cycle()
+            .take(spam.transactions)
+            .enumerate()
+        {
+            let outputs = spam_wallet
+                .addresses
+                .iter()
+                .take(spam.outputs)
+                .map(|address| (address.to_string(), SPAM_OUTPUT_VALUE.into()))
+                .collect();
+            let txid = spam_wallet
+                .wallet
+                .client
+                .with_wallet(spam_wallet.wallet.name())?
+                .sendmany("", outputs, None, Some(spam.fee_rates.fee_rate(index)))
+                .await?;
+
+            txids.push(Txid::from_hex(&txid).map_err(crate::bitcoind_rpc::Error::from)?);
+        }
+
+        Ok(txids)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reject_distributions_without_fee_rate() {
+        assert!(FeeRateDistribution::Fixed(1).validate().is_ok());
+        assert!(FeeRateDistribution::Uniform { min: 2, max: 2 }
+            .validate()
+            .is_ok());
+        assert!(matches!(
+            FeeRateDistribution::Uniform { min: 3, max: 2 }.validate(),
+            Err(Error::InvalidFeeRates(_))
+        ));
+        assert!(matches!(
+            FeeRateDistribution::Cycle(Vec::new()).validate(),
+            Err(Error::InvalidFeeRates(_))
+        ));
+    }
+
+    #[test]
+    fn flood_rejects_invalid_distribution() {
+        let mut congestion = Congestion {
+            wallets: Vec::new(),
+        };
+
+        let res = futures::executor::block_on(congestion.flood(&Spam {
+            transactions: 1,
+            outputs: 1,
+            fee_rates: FeeRateDistribution::Cycle(Vec::new()),
+        }));
+
+        assert!(matches!(res, Err(Error::InvalidFeeRates(_))));
+    }
+
+    #[cfg(feature = "test-docker")]
+    #[tokio::test]
+    async fn low_fee_transaction_stays_unconfirmed() {
+        use crate::image::BitcoindArgs;
+        use crate::wallet::SendOptions;
+
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind =
+            Bitcoind::with_args(&tc_client, BitcoindArgs::default().block_max_weight(40_000))
+                .unwrap();
+        let client = Client::new(bitcoind.node_url.clone());
+        let wallet = Wallet::new(&bitcoind.wallet_name, bitcoind.node_url.clone())
+            .await
+            .unwrap();
+        let address = wallet.new_address().await.unwrap();
+        client
+            .generatetoaddress(110, address.clone())
+            .await
+            .unwrap();
+
+        let mut congestion = Congestion::new(&bitcoind, 2, 100).await.unwrap();
+        let spam_txids = congestion
+            .flood(&Spam {
+                transactions: 200,
+                outputs: 2,
+                fee_rates: FeeRateDistribution::Uniform { min: 10, max: 50 },
+            })
+            .await
+            .unwrap();
+        assert_eq!(spam_txids.len(), 200);
+
+        let low_fee_txid = wallet
+            .send_to_address_with(
+                address.clone(),
+                Amount::from_btc(1.0).unwrap(),
+                SendOptions {
+                    fee_rate: Some(1),
+                    ..SendOptions::default()
+                },
+            )
+            .await
+            .unwrap();
+        client.generatetoaddress(2, address).await.unwrap();
+
+        let mempool = client.getrawmempool().await.unwrap();
+        assert!(mempool.contains(&low_fee_txid));
+        assert!(mempool.len() > 1);
+    }
+}
@@ -0,0 +1,172 @@
+//! Races of conflicting transactions across partitioned nodes
+//!
+//! A node rejects a transaction conflicting with one of its mempool transactions unless it is a
+//! BIP 125 replacement. Conflicts are hence broadcast to different nodes while they are isolated
+//! from each other, the conflict mined first wins once the nodes are reconnected.
+
+use crate::bitcoind_rpc::Client;
+use crate::bitcoind_rpc_api::BitcoindRpcApi;
+use crate::{Bitcoind, Error, Result};
+use bitcoin::secp256k1::{rand, Secp256k1, SecretKey};
+use bitcoin::{Address, PublicKey, Transaction, Txid};
+use std::time::Duration;
+
+/// How many times the tips of the nodes are compared before giving up on their sync
+const SYNC_ATTEMPTS: u32 = 300;
+
+/// Broadcast `conflicts[i]` to `nodes[i]` while the nodes are isolated, then mine a block on
+/// `nodes[miner]` and reconnect the nodes. Returns the txid of the conflict confirmed in the
+/// chain all nodes agreed on.
+///
+/// # Panics
+///
+/// If there is not one conflict per node, or `miner` is not the index of one of the nodes.
+pub async fn partitioned(
+    nodes: &[&Bitcoind<'_>],
+    conflicts: &[Transaction],
+    miner: usize,
+) -> Result<Txid> {
+    assert_eq!(nodes.len(), conflicts.len(), "one conflict per node");
+    assert!(miner < nodes.len(), "the miner is one of the nodes");
+
+    let clients = nodes
+        .iter()
+        .map(|node| Client::new(node.node_url.clone()))
+        .collect::<Vec<_>>();
+
+    for client in clients.iter() {
+        client.setnetworkactive(false).await?;
+    }
+    for client in clients.iter() {
+        while client.getconnectioncount().await? != 0 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    for (client, conflict) in clients.iter().zip(conflicts) {
+        client.sendrawtransaction(conflict.clone().into()).await?;
+    }
+    let reward_address = burn_address(&clients[miner]).await?;
+    let block_hash = nodes[miner].generate(1, reward_address).await?[0];
+
+    for client in clients.iter() {
+        client.setnetworkactive(true).await?;
+    }
+    for pair in nodes.windows(2) {
+        pair[0].connect(pair[1]).await?;
+    }
+    wait_for_sync(&clients).await?;
+
+    let block = clients[miner].getblock(&block_hash).await?;
+    conflicts
+        .iter()
+        .map(Transaction::txid)
+        .find(|txid| block.tx.contains(txid))
+        .ok_or(Error::NoConflictConfirmed)
+}
+
+async fn wait_for_sync(clients: &[Client]) -> Result<()> {
+    for _ in 0..SYNC_ATTEMPTS {
+        let mut tips = Vec::with_capacity(clients.len());
+        for client in clients {
+            tips.push(client.getblockchaininfo().await?.best_block_hash);
+        }
+        if tips.windows(2).all(|pair| pair[0] == pair[1]) {
+            return Ok(());
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    Err(Error::NodesNotSynced)
+}
+
+/// An address of a freshly generated key, which is then forgotten.
+async fn burn_address(client: &Client) -> Result<Address> {
+    let secp = Secp256k1::signing_only();
+    let public_key = PublicKey::new(SecretKey::new(&mut rand::thread_rng()).public_key(&secp));
+
+    Ok(Address::p2wpkh(&public_key, client.network().await?).expect("key is compressed"))
+}
+
+#[cfg(all(test, feature = "test-docker"))]
+mod test {
+    use super::*;
+    use crate::wallet::SendOptions;
+    use crate::Wallet;
+    use bitcoin::Amount;
+
+    #[tokio::test]
+    async fn replace_transaction() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind = Bitcoind::new(&tc_client).unwrap();
+        let client = Client::new(bitcoind.node_url.clone());
+
+        // No block is mined in the background, which could confirm the original transaction
+        let wallet = Wallet::new("wallet", bitcoind.node_url.clone())
+            .await
+            .unwrap();
+        let address = wallet.new_address().await.unwrap();
+        client.generatetoaddress(101, address).await.unwrap();
+
+        let txid = wallet
+            .send_to_address_with(
+                wallet.new_address().await.unwrap(),
+                Amount::from_btc(1.0).unwrap(),
+                SendOptions {
+                    replaceable: Some(true),
+                    fee_rate: Some(1),
+                },
+            )
+            .await
+            .unwrap();
+        let destination = wallet.new_address().await.unwrap();
+        let conflict_txid = wallet.double_spend(txid, &destination, 10).await.unwrap();
+
+        let mempool = client.getrawmempool().await.unwrap();
+        assert!(mempool.contains(&conflict_txid));
+        assert!(!mempool.contains(&txid));
+    }
+
+    #[tokio::test]
+    async fn conflict_mined_on_partition_wins() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let alice = Bitcoind::new(&tc_client).unwrap();
+        let bob = Bitcoind::new(&tc_client).unwrap();
+        let alice_client = Client::new(alice.node_url.clone());
+        let bob_client = Client::new(bob.node_url.clone());
+
+        let wallet = Wallet::new("wallet", alice.node_url.clone()).await.unwrap();
+        let address = wallet.new_address().await.unwrap();
+        alice_client.generatetoaddress(101, address).await.unwrap();
+        alice.connect(&bob).await.unwrap();
+        wait_for_sync(&[alice_client, bob_client]).await.unwrap();
+
+        // Signed but not broadcast yet, so that it does not reach bob before the partition
+        let psbt = wallet
+            .fund_psbt(
+                wallet.new_address().await.unwrap(),
+                Amount::from_btc(1.0).unwrap(),
+            )
+            .await
+            .unwrap();
+        let psbt = wallet.wallet_process_psbt(psbt.into()).await.unwrap();
+        let original = wallet
+            .finalize_psbt(psbt.into())
+            .await
+            .unwrap()
+            .transaction()
+            .unwrap()
+            .unwrap();
+        let conflict = wallet
+            .build_double_spend(&original, &wallet.new_address().await.unwrap(), 5)
+            .await
+            .unwrap();
+
+        let winner = partitioned(&[&alice, &bob], &[original, conflict.clone()], 1)
+            .await
+            .unwrap();
+
+        assert_eq!(winner, conflict.txid());
+    }
+}
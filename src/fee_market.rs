@@ -0,0 +1,50 @@
+//! Fee market profiles used to seed the fee estimator of bitcoind
+//!
+//! bitcoind only estimates fees once it has seen enough transactions go from its mempool into
+//! blocks, which never happens on a fresh regtest node. See [`crate::Bitcoind::seed_fee_market`].
+
+/// The distribution of the fee rates of the transactions confirmed in each block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeProfile {
+    /// Fee rates of a few sat/vB
+    Low,
+    /// Mostly low fee rates, with some transactions paying much more
+    Spiky,
+    /// High fee rates across the board
+    Congested,
+}
+
+impl FeeProfile {
+    /// The fee rates, in sat/vB, of the transactions sent for each block.
+    pub fn fee_rates(&self) -> &'static [u64] {
+        match self {
+            FeeProfile::Low => &[1, 1, 2, 2, 3, 3, 4, 5],
+            FeeProfile::Spiky => &[1, 2, 2, 3, 50, 2, 3, 100],
+            FeeProfile::Congested => &[20, 30, 45, 60, 80, 100, 150, 200],
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-docker"))]
+mod test {
+    use super::*;
+    use crate::{Bitcoind, BitcoindRpcApi, Client};
+
+    #[tokio::test]
+    async fn seeded_fee_market_yields_estimates() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind = Bitcoind::new(&tc_client).unwrap();
+        bitcoind.init(5).await.unwrap();
+        let client = Client::new(bitcoind.node_url.clone());
+
+        let estimate = client.estimatesmartfee(2, None).await.unwrap();
+        assert!(estimate.fee_rate.is_none());
+
+        bitcoind.seed_fee_market(FeeProfile::Spiky).await.unwrap();
+
+        let estimate = client.estimatesmartfee(2, None).await.unwrap();
+        assert!(estimate.fee_rate.is_some());
+        let raw_estimate = client.estimaterawfee(2).await.unwrap();
+        assert!(raw_estimate.short.is_some());
+    }
+}
@@ -0,0 +1,246 @@
+//! Record and replay of the RPC traffic of a [`Client`](crate::Client)
+//!
+//! A client built with [`ClientBuilder::record`](crate::bitcoind_rpc::ClientBuilder::record)
+//! keeps every call it makes, and the response of the node, and writes them to a JSON fixture
+//! file with [`Client::finish_recording`](crate::Client::finish_recording) or once dropped. A
+//! client built with [`ClientBuilder::replay`](crate::bitcoind_rpc::ClientBuilder::replay)
+//! serves the responses of such a file instead of reaching a node, so that a test recorded once
+//! against a bitcoind container runs without Docker afterwards.
+//!
+//! The calls are matched on their method, wallet and parameters. A call made several times with
+//! the same parameters, such as `getblockcount` while blocks are mined, gets the recorded
+//! responses in order.
+
+use jsonrpc_client::JsonRpcError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+/// A call and the response of the node, as stored in a fixture file.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    pub method: String,
+    /// The wallet of the `/wallet/<name>` endpoint the call was sent to
+    #[serde(default)]
+    pub wallet: Option<String>,
+    #[serde(default)]
+    pub params: Value,
+    #[serde(default)]
+    pub result: Value,
+    #[serde(default)]
+    pub error: Option<JsonRpcError>,
+}
+
+impl Exchange {
+    fn matches(&self, method: &str, wallet: Option<&str>, params: &Value) -> bool {
+        self.method == method && self.wallet.as_deref() == wallet && &self.params == params
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Fixture {
+    Record {
+        path: PathBuf,
+        exchanges: Mutex<Vec<Exchange>>,
+    },
+    Replay {
+        path: PathBuf,
+        /// The exchanges not replayed yet
+        exchanges: Mutex<Vec<Exchange>>,
+    },
+}
+
+impl Fixture {
+    /// Start recording to `path`, truncating the file.
+    pub(crate) fn record(path: &Path) -> io::Result<Self> {
+        let fixture = Fixture::Record {
+            path: path.to_path_buf(),
+            exchanges: Mutex::new(Vec::new()),
+        };
+        fixture.save(&[])?;
+
+        Ok(fixture)
+    }
+
+    pub(crate) fn replay(path: &Path) -> io::Result<Self> {
+        let exchanges = serde_json::from_reader(File::open(path)?)?;
+
+        Ok(Fixture::Replay {
+            path: path.to_path_buf(),
+            exchanges: Mutex::new(exchanges),
+        })
+    }
+
+    /// The recorded response to the call, `None` when recording.
+    ///
+    /// # Panics
+    ///
+    /// If the call is not in the replayed fixture, or all its recorded responses were served.
+    pub(crate) fn replay_call(
+        &self,
+        method: &str,
+        wallet: Option<&str>,
+        params: &Value,
+    ) -> Option<Exchange> {
+        let (path, exchanges) = match self {
+            Fixture::Record { .. } => return None,
+            Fixture::Replay { path, exchanges } => (path, exchanges),
+        };
+
+        let mut exchanges = exchanges.lock().unwrap_or_else(PoisonError::into_inner);
+        match exchanges
+            .iter()
+            .position(|exchange| exchange.matches(method, wallet, params))
+        {
+            Some(index) => Some(exchanges.remove(index)),
+            None => panic!(
+                "unexpected call to {} (wallet: {:?}, params: {}), not recorded in {}",
+                method,
+                wallet,
+                params,
+                path.display()
+            ),
+        }
+    }
+
+    /// Append `exchange` to the recorded fixture, it is written by [`Fixture::finish`] or when
+    /// the fixture is dropped.
+    pub(crate) fn record_call(&self, exchange: Exchange) {
+        if let Fixture::Record { exchanges, .. } = self {
+            exchanges
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(exchange);
+        }
+    }
+
+    /// Write the exchanges recorded so far, does nothing when replaying.
+    pub(crate) fn finish(&self) -> io::Result<()> {
+        match self {
+            Fixture::Record { exchanges, .. } => {
+                self.save(&exchanges.lock().unwrap_or_else(PoisonError::into_inner))
+            }
+            Fixture::Replay { .. } => Ok(()),
+        }
+    }
+
+    fn save(&self, exchanges: &[Exchange]) -> io::Result<()> {
+        let path = match self {
+            Fixture::Record { path, .. } | Fixture::Replay { path, .. } => path,
+        };
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), exchanges)?;
+
+        Ok(())
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        if let Err(error) = self.finish() {
+            if let Fixture::Record { path, .. } = self {
+                tracing::warn!(path = %path.display(), %error, "failed to write fixture");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BitcoindRpcApi, Client};
+    use bitcoin::secp256k1::rand::{thread_rng, Rng};
+    use serde_json::json;
+
+    fn fixture_path() -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "bitcoin-harness-fixture-{}.json",
+            thread_rng().gen::<u64>()
+        ))
+    }
+
+    fn write_fixture(exchanges: &[Exchange]) -> PathBuf {
+        let path = fixture_path();
+        serde_json::to_writer(File::create(&path).unwrap(), exchanges).unwrap();
+
+        path
+    }
+
+    #[tokio::test]
+    async fn replay_recorded_responses_in_order() {
+        let exchange = |count: u32| Exchange {
+            method: "getblockcount".to_string(),
+            wallet: None,
+            params: Value::Null,
+            result: json!(count),
+            error: None,
+        };
+        let path = write_fixture(&[exchange(1), exchange(2)]);
+        let client = Client::builder("http://localhost:1".parse().unwrap())
+            .replay(&path)
+            .build()
+            .unwrap();
+
+        assert_eq!(client.getblockcount().await.unwrap(), 1);
+        assert_eq!(client.getblockcount().await.unwrap(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "unexpected call to getblockcount")]
+    async fn unexpected_call_panics() {
+        let path = write_fixture(&[]);
+        let client = Client::builder("http://localhost:1".parse().unwrap())
+            .replay(&path)
+            .build()
+            .unwrap();
+
+        let _ = client.getblockcount().await;
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test]
+    async fn replay_recorded_calls() {
+        use crate::mock::MockBitcoind;
+        use crate::Wallet;
+
+        let path = fixture_path();
+        let bitcoind = MockBitcoind::new().unwrap();
+        let client = Client::builder(bitcoind.node_url.clone())
+            .record(&path)
+            .build()
+            .unwrap();
+        let wallet = Wallet::with_client("wallet", client.clone()).await.unwrap();
+        let address = wallet.new_address().await.unwrap();
+        let hashes = client.generatetoaddress(1, address.clone()).await.unwrap();
+        client.finish_recording().unwrap();
+        drop(bitcoind);
+
+        let client = Client::builder("http://localhost:1".parse().unwrap())
+            .replay(&path)
+            .build()
+            .unwrap();
+        let wallet = Wallet::with_client("wallet", client.clone()).await.unwrap();
+
+        assert_eq!(wallet.new_address().await.unwrap(), address);
+        assert_eq!(client.generatetoaddress(1, address).await.unwrap(), hashes);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn failed_write_is_a_fixture_error() {
+        use crate::bitcoind_rpc::Error;
+
+        let dir = fixture_path();
+        std::fs::create_dir(&dir).unwrap();
+        let client = Client::builder("http://localhost:1".parse().unwrap())
+            .record(&dir.join("fixture.json"))
+            .build()
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(client.finish_recording(), Err(Error::Fixture(_))));
+    }
+}
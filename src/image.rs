@@ -0,0 +1,133 @@
+//! The bitcoind docker image, extending the coblox image with arbitrary bitcoind arguments
+
+use std::io;
+use std::path::Path;
+use testcontainers::core::WaitFor;
+use testcontainers::images::coblox_bitcoincore::{self, BitcoinCoreImageArgs};
+use testcontainers::{Image, ImageArgs};
+
+/// Where a bitcoind binary mounted from the host is found in the container
+const MOUNTED_BITCOIND: &str = "/usr/local/bin/bitcoind-host";
+
+/// A coblox image tag recent enough for the tests of features the default image lacks
+#[cfg(all(test, feature = "test-docker"))]
+pub(crate) const RECENT_CORE_TAG: &str = "24.0.1";
+
+#[derive(Debug, Default)]
+pub struct BitcoindImage {
+    inner: coblox_bitcoincore::BitcoinCore,
+    tag: Option<String>,
+    /// The host path of a bitcoind binary and where it is mounted in the container
+    volume: Option<(String, String)>,
+}
+
+impl BitcoindImage {
+    /// The coblox image at `tag`, e.g. `"0.20.1"`, instead of the default `0.21.0`.
+    pub fn with_tag(tag: impl Into<String>) -> Self {
+        Self {
+            tag: Some(tag.into()),
+            ..Self::default()
+        }
+    }
+
+    /// The default image running the bitcoind `binary` of the host instead of its own.
+    ///
+    /// The binary is mounted in the container, it must be a Linux build which only links
+    /// against glibc, such as the release binaries of bitcoincore.org. A relative path is
+    /// resolved against the current directory, it fails if the binary does not exist.
+    pub fn with_binary(binary: &Path) -> io::Result<Self> {
+        let binary = binary.canonicalize()?;
+
+        Ok(Self {
+            volume: Some((binary.display().to_string(), MOUNTED_BITCOIND.to_string())),
+            ..Self::default()
+        })
+    }
+}
+
+impl Image for BitcoindImage {
+    type Args = BitcoindArgs;
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn tag(&self) -> String {
+        self.tag.clone().unwrap_or_else(|| self.inner.tag())
+    }
+
+    fn ready_conditions(&self) -> Vec<WaitFor> {
+        self.inner.ready_conditions()
+    }
+
+    fn volumes(&self) -> Box<dyn Iterator<Item = (&String, &String)> + '_> {
+        Box::new(
+            self.volume
+                .iter()
+                .map(|(host, container)| (host, container)),
+        )
+    }
+
+    fn entrypoint(&self) -> Option<String> {
+        self.volume.as_ref().map(|(_, container)| container.clone())
+    }
+}
+
+/// The arguments of the coblox image followed by `extra` arguments passed as is to bitcoind.
+#[derive(Debug, Clone, Default)]
+pub struct BitcoindArgs {
+    pub core: BitcoinCoreImageArgs,
+    pub extra: Vec<String>,
+}
+
+impl BitcoindArgs {
+    /// Arguments to run a private signet whose blocks must satisfy `challenge`, the RPC
+    /// port is kept to the regtest one.
+    pub fn signet(challenge: &bitcoin::Script) -> Self {
+        Self {
+            core: BitcoinCoreImageArgs {
+                network: coblox_bitcoincore::Network::Mainnet,
+                ..BitcoinCoreImageArgs::default()
+            },
+            extra: vec![
+                "-signet".to_string(),
+                format!("-signetchallenge={:x}", challenge),
+                format!("-rpcport={}", crate::BITCOIND_RPC_PORT),
+            ],
+        }
+    }
+
+    /// Limit the weight of the blocks mined by the node, including with `generatetoaddress`.
+    pub fn block_max_weight(mut self, weight: u64) -> Self {
+        self.extra.push(format!("-blockmaxweight={}", weight));
+        self
+    }
+}
+
+impl ImageArgs for BitcoindArgs {
+    fn into_iterator(self) -> Box<dyn Iterator<Item = String>> {
+        Box::new(self.core.into_iterator().chain(self.extra))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mount_binary_at_absolute_path() {
+        let image = BitcoindImage::with_binary(Path::new("Cargo.toml")).unwrap();
+
+        let (host, container) = image.volumes().next().unwrap();
+        assert!(Path::new(host).is_absolute());
+        assert!(host.ends_with("Cargo.toml"));
+        assert_eq!(container, MOUNTED_BITCOIND);
+    }
+
+    #[test]
+    fn reject_missing_binary() {
+        let res = BitcoindImage::with_binary(Path::new("bin/bitcoind-missing"));
+
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -63,13 +63,40 @@
 //! # }
 //! ```
 
+pub mod amount;
 pub mod bitcoind_rpc;
 pub mod bitcoind_rpc_api;
+pub mod block_builder;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod capabilities;
+pub mod congestion;
+pub mod double_spend;
+pub mod fee_market;
+pub mod fixture;
+pub mod image;
+pub mod matrix;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod multisig;
+pub mod retry;
+pub mod signet;
 pub mod wallet;
 
+use crate::bitcoind_rpc::CreateWalletOptions;
+use crate::bitcoind_rpc_api::AddNodeCommand;
+use crate::fee_market::FeeProfile;
+use crate::image::{BitcoindArgs, BitcoindImage};
+use bitcoin::BlockHash;
+use bitcoincore_rpc_json::GetBlockchainInfoResult;
 use reqwest::Url;
+use std::ffi::OsStr;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::time::Duration;
-use testcontainers::{clients, images::coblox_bitcoincore::BitcoinCore, Container};
+use testcontainers::{clients, Container};
 
 pub use crate::bitcoind_rpc::Client;
 pub use crate::bitcoind_rpc_api::BitcoindRpcApi;
@@ -78,21 +105,61 @@ pub use crate::wallet::Wallet;
 pub type Result<T> = std::result::Result<T, Error>;
 
 const BITCOIND_RPC_PORT: u16 = 18443;
+/// How long `init` waits for the node to be ready
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+const REGTEST_P2P_PORT: u16 = 18444;
+const SIGNET_P2P_PORT: u16 = 38333;
 
 #[derive(Debug)]
 pub struct Bitcoind<'c> {
-    pub container: Container<'c, BitcoinCore>,
+    pub container: Container<'c, BitcoindImage>,
     pub node_url: Url,
     pub wallet_name: String,
+    /// Signs the blocks when running a private signet
+    pub signet_miner: Option<signet::Miner>,
 }
 
 impl<'c> Bitcoind<'c> {
     /// Starts a new regtest bitcoind container
     pub fn new(client: &'c clients::Cli) -> Result<Self> {
-        let container = client.run(BitcoinCore::default());
+        Self::start(
+            client,
+            BitcoindImage::default(),
+            BitcoindArgs::default(),
+            None,
+        )
+    }
+
+    /// Starts a new regtest bitcoind container with additional arguments
+    pub fn with_args(client: &'c clients::Cli, args: BitcoindArgs) -> Result<Self> {
+        Self::start(client, BitcoindImage::default(), args, None)
+    }
+
+    /// Starts a new regtest bitcoind container of another image tag or bitcoind binary, see
+    /// [`matrix::for_each_core_version`] to run a test against several versions.
+    pub fn with_image(client: &'c clients::Cli, image: BitcoindImage) -> Result<Self> {
+        Self::start(client, image, BitcoindArgs::default(), None)
+    }
+
+    /// Starts a new bitcoind container running a private signet, whose blocks are signed with
+    /// a freshly generated challenge key.
+    pub fn new_signet(client: &'c clients::Cli) -> Result<Self> {
+        let miner = signet::Miner::new();
+        let args = BitcoindArgs::signet(miner.challenge());
+
+        Self::start(client, BitcoindImage::default(), args, Some(miner))
+    }
+
+    fn start(
+        client: &'c clients::Cli,
+        image: BitcoindImage,
+        args: BitcoindArgs,
+        signet_miner: Option<signet::Miner>,
+    ) -> Result<Self> {
+        let container = client.run((image, args));
         let port = container.get_host_port_ipv4(BITCOIND_RPC_PORT);
 
-        let auth = &container.image_args().rpc_auth;
+        let auth = &container.image_args().core.rpc_auth;
         let url = format!(
             "http://{}:{}@localhost:{}",
             auth.username(),
@@ -107,17 +174,20 @@ impl<'c> Bitcoind<'c> {
             container,
             node_url: url,
             wallet_name,
+            signet_miner,
         })
     }
 
-    /// Create a test wallet, generate enough block to fund it and activate segwit.
+    /// Create a test wallet, generate enough block to fund it and activate segwit. The wallet
+    /// is a descriptor wallet from bitcoind 23.0.
     /// Generate enough blocks to make the passed `spendable_quantity` spendable.
     /// Spawn a tokio thread to mine a new block every second.
     pub async fn init(&self, spendable_quantity: u32) -> Result<()> {
+        self.wait_until_ready(READY_TIMEOUT).await?;
         let bitcoind_client = Client::new(self.node_url.clone());
 
         bitcoind_client
-            .createwallet(&self.wallet_name, None, None, None, None)
+            .create_wallet(&self.wallet_name, CreateWalletOptions::default())
             .await?;
 
         let reward_address = bitcoind_client
@@ -125,14 +195,59 @@ impl<'c> Bitcoind<'c> {
             .getnewaddress(None, None)
             .await?;
 
-        bitcoind_client
-            .generatetoaddress(101 + spendable_quantity, reward_address.clone())
-            .await?;
-        let _ = tokio::spawn(mine(bitcoind_client, reward_address));
+        generate(
+            &bitcoind_client,
+            self.signet_miner.as_ref(),
+            101 + spendable_quantity,
+            reward_address.clone(),
+        )
+        .await?;
+        tokio::spawn(mine(
+            bitcoind_client,
+            self.signet_miner.clone(),
+            reward_address,
+        ));
 
         Ok(())
     }
 
+    /// Wait until the node answers RPC calls and is done with the initial block download.
+    ///
+    /// A fresh regtest or signet node stays in initial block download until a block is mined, it
+    /// is ready as long as it has neither blocks nor headers. If the node still does not answer
+    /// when `timeout` elapses, the error of its last answer is returned.
+    pub async fn wait_until_ready(&self, timeout: Duration) -> Result<()> {
+        let bitcoind_client = Client::new(self.node_url.clone());
+        let mut last_error = None;
+
+        let poll = async {
+            loop {
+                match (
+                    bitcoind_client.getblockchaininfo().await,
+                    bitcoind_client.getnetworkinfo().await,
+                ) {
+                    (Ok(blockchain_info), Ok(_)) => {
+                        last_error = None;
+                        if is_ready(&blockchain_info) {
+                            return;
+                        }
+                    }
+                    (Err(e), _) | (_, Err(e)) => last_error = Some(e),
+                }
+
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        };
+
+        match tokio::time::timeout(timeout, poll).await {
+            Ok(()) => Ok(()),
+            Err(_) => match last_error {
+                Some(e) => Err(e.into()),
+                None => Err(Error::NotReady(timeout)),
+            },
+        }
+    }
+
     /// Send Bitcoin to the specified address, limited to the spendable bitcoin quantity.
     pub async fn mint(&self, address: bitcoin::Address, amount: bitcoin::Amount) -> Result<()> {
         let bitcoind_client = Client::new(self.node_url.clone());
@@ -146,7 +261,72 @@ impl<'c> Bitcoind<'c> {
             .with_wallet(&self.wallet_name)?
             .getnewaddress(None, None)
             .await?;
-        bitcoind_client.generatetoaddress(1, reward_address).await?;
+        self.generate(1, reward_address).await?;
+
+        Ok(())
+    }
+
+    /// Confirm transactions paying the fee rates of `profile`, one block after the other,
+    /// until `estimatesmartfee` returns an estimate. The test wallet must be funded with
+    /// [`Bitcoind::init`].
+    pub async fn seed_fee_market(&self, profile: FeeProfile) -> Result<()> {
+        const MAX_BLOCKS: u32 = 50;
+        const CONF_TARGET: u16 = 2;
+
+        let bitcoind_client = Client::new(self.node_url.clone());
+        let wallet_client = bitcoind_client.with_wallet(&self.wallet_name)?;
+        let address = wallet_client.getnewaddress(None, None).await?;
+        let amount = bitcoin::Amount::from_sat(100_000);
+
+        for _ in 0..MAX_BLOCKS {
+            for fee_rate in profile.fee_rates() {
+                wallet_client
+                    .sendtoaddress(address.clone(), amount.into(), None, Some(*fee_rate))
+                    .await?;
+            }
+            self.generate(1, address.clone()).await?;
+
+            let estimate = bitcoind_client.estimatesmartfee(CONF_TARGET, None).await?;
+            if estimate.fee_rate.is_some() {
+                return Ok(());
+            }
+        }
+
+        Err(Error::FeeEstimationUnavailable)
+    }
+
+    /// Mine `nblocks` blocks paying their reward to `address`.
+    pub async fn generate(
+        &self,
+        nblocks: u32,
+        address: bitcoin::Address,
+    ) -> Result<Vec<BlockHash>> {
+        let bitcoind_client = Client::new(self.node_url.clone());
+
+        generate(
+            &bitcoind_client,
+            self.signet_miner.as_ref(),
+            nblocks,
+            address,
+        )
+        .await
+    }
+
+    /// The address other nodes of the docker bridge network can reach this node's P2P port at.
+    pub fn p2p_address(&self) -> SocketAddr {
+        let port = match self.signet_miner {
+            Some(_) => SIGNET_P2P_PORT,
+            None => REGTEST_P2P_PORT,
+        };
+
+        SocketAddr::new(self.container.get_bridge_ip_address(), port)
+    }
+
+    /// Open a P2P connection to `peer`.
+    pub async fn connect(&self, peer: &Bitcoind<'_>) -> Result<()> {
+        Client::new(self.node_url.clone())
+            .addnode(peer.p2p_address(), AddNodeCommand::Onetry)
+            .await?;
 
         Ok(())
     }
@@ -154,25 +334,162 @@ impl<'c> Bitcoind<'c> {
     pub fn container_id(&self) -> &str {
         self.container.id()
     }
+
+    /// Copy a file, such as a wallet backup, from the bitcoind container to the host.
+    pub async fn copy_from_container(&self, container_path: &Path, host_path: &Path) -> Result<()> {
+        let source = format!("{}:{}", self.container_id(), container_path.display());
+
+        docker_cp(source.as_ref(), host_path.as_os_str()).await
+    }
+
+    /// Copy a file from the host to the bitcoind container.
+    pub async fn copy_to_container(&self, host_path: &Path, container_path: &Path) -> Result<()> {
+        let destination = format!("{}:{}", self.container_id(), container_path.display());
+
+        docker_cp(host_path.as_os_str(), destination.as_ref()).await
+    }
+}
+
+async fn docker_cp(source: &OsStr, destination: &OsStr) -> Result<()> {
+    let output = tokio::process::Command::new("docker")
+        .arg("cp")
+        .arg(source)
+        .arg(destination)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(Error::DockerCp(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+
+    Ok(())
 }
 
-async fn mine(bitcoind_client: Client, reward_address: bitcoin::Address) -> Result<()> {
+async fn mine(
+    bitcoind_client: Client,
+    signet_miner: Option<signet::Miner>,
+    reward_address: bitcoin::Address,
+) -> Result<()> {
     loop {
         tokio::time::sleep(Duration::from_secs(1)).await;
-        bitcoind_client
-            .generatetoaddress(1, reward_address.clone())
-            .await?;
+        generate(
+            &bitcoind_client,
+            signet_miner.as_ref(),
+            1,
+            reward_address.clone(),
+        )
+        .await?;
+    }
+}
+
+async fn generate(
+    bitcoind_client: &Client,
+    signet_miner: Option<&signet::Miner>,
+    nblocks: u32,
+    address: bitcoin::Address,
+) -> Result<Vec<BlockHash>> {
+    match signet_miner {
+        Some(miner) => {
+            miner
+                .generate_to_address(bitcoind_client, nblocks, &address)
+                .await
+        }
+        None => Ok(bitcoind_client.generatetoaddress(nblocks, address).await?),
     }
 }
 
+fn is_ready(blockchain_info: &GetBlockchainInfoResult) -> bool {
+    let empty_test_chain = matches!(blockchain_info.chain.as_str(), "regtest" | "signet")
+        && blockchain_info.blocks == 0
+        && blockchain_info.headers == 0;
+
+    !blockchain_info.initial_block_download || empty_test_chain
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Bitcoin Rpc: ")]
     BitcoindRpc(#[from] bitcoind_rpc::Error),
     #[error("Json Rpc: ")]
-    JsonRpc(#[from] jsonrpc_client::Error<reqwest::Error>),
+    JsonRpc(#[from] jsonrpc_client::Error<bitcoind_rpc::TransportError>),
     #[error("Url Parsing: ")]
     UrlParseError(#[from] url::ParseError),
     #[error("Docker port not exposed: ")]
     PortNotExposed(u16),
+    #[error("Block rejected: {0}")]
+    BlockRejected(String),
+    #[error("Docker cp: {0}")]
+    DockerCp(String),
+    #[error("Io: ")]
+    Io(#[from] std::io::Error),
+    #[error("Bip32: ")]
+    Bip32(#[from] bitcoin::util::bip32::Error),
+    #[error("Descriptor import failed: {0}")]
+    ImportDescriptors(String),
+    #[error("Psbt is not fully signed")]
+    IncompletePsbt,
+    #[error("Node not ready after {0:?}")]
+    NotReady(Duration),
+    #[error("None of the conflicting transactions was confirmed")]
+    NoConflictConfirmed,
+    #[error("Nodes did not agree on the chain tip")]
+    NodesNotSynced,
+    #[error("No fee estimate after seeding the fee market")]
+    FeeEstimationUnavailable,
+    #[error("Invalid multisig threshold {threshold} of {n}")]
+    InvalidThreshold { threshold: usize, n: usize },
+    #[error("No fee rate can be picked from {0:?}")]
+    InvalidFeeRates(congestion::FeeRateDistribution),
+    #[error("No active wpkh descriptor in wallet {0}")]
+    CosignerKeyNotFound(String),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use std::collections::HashMap;
+
+    fn blockchain_info(
+        chain: &str,
+        blocks: u64,
+        headers: u64,
+        initial_block_download: bool,
+    ) -> GetBlockchainInfoResult {
+        GetBlockchainInfoResult {
+            chain: chain.to_string(),
+            blocks,
+            headers,
+            best_block_hash: BlockHash::all_zeros(),
+            difficulty: 1.0,
+            median_time: 0,
+            verification_progress: 0.0,
+            initial_block_download,
+            chain_work: Vec::new(),
+            size_on_disk: 0,
+            pruned: false,
+            prune_height: None,
+            automatic_pruning: None,
+            prune_target_size: None,
+            softforks: HashMap::new(),
+            warnings: String::new(),
+        }
+    }
+
+    #[test]
+    fn node_in_initial_block_download_is_not_ready() {
+        assert!(!is_ready(&blockchain_info("main", 1000, 1000, true)));
+        assert!(!is_ready(&blockchain_info("regtest", 1, 1, true)));
+        assert!(!is_ready(&blockchain_info("signet", 0, 10, true)));
+        assert!(is_ready(&blockchain_info("main", 1000, 1000, false)));
+    }
+
+    #[test]
+    fn empty_test_chain_is_ready() {
+        assert!(is_ready(&blockchain_info("regtest", 0, 0, true)));
+        assert!(is_ready(&blockchain_info("signet", 0, 0, true)));
+        assert!(!is_ready(&blockchain_info("main", 0, 0, true)));
+    }
 }
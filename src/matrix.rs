@@ -0,0 +1,317 @@
+//! Run a test against several Bitcoin Core versions
+//!
+//! [`for_each_core_version`] starts a node of each [`CoreVersion`] in turn, with
+//! [`Bitcoind::with_image`], and runs the test body against it. A failing or panicking body does
+//! not stop the run, the [`Report`] tells which versions passed.
+//!
+//! ```rust,no_run
+//! use bitcoin_harness::matrix::{for_each_core_version, CoreVersion};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let tc_client = testcontainers::clients::Cli::default();
+//! let report = for_each_core_version(&tc_client, CoreVersion::from_env(), |bitcoind| async move {
+//!     bitcoind.init(1).await
+//! })
+//! .await;
+//!
+//! report.assert_passed();
+//! # }
+//! ```
+
+use crate::image::BitcoindImage;
+use crate::Bitcoind;
+use futures::FutureExt;
+use std::any::Any;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use testcontainers::clients;
+
+/// Comma separated list of the versions returned by [`CoreVersion::from_env`]
+pub const CORE_VERSIONS_ENV: &str = "BITCOIN_HARNESS_CORE_VERSIONS";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoreVersion {
+    /// A tag of the coblox image, e.g. `"0.20.1"`
+    Image(String),
+    /// A bitcoind binary of the host, see [`BitcoindImage::with_binary`]
+    Binary(PathBuf),
+}
+
+impl CoreVersion {
+    /// The versions listed in [`CORE_VERSIONS_ENV`], the default image if it is not set.
+    ///
+    /// # Panics
+    ///
+    /// If the variable is set but lists no version.
+    pub fn from_env() -> Vec<CoreVersion> {
+        let versions = match std::env::var(CORE_VERSIONS_ENV) {
+            Ok(versions) => versions,
+            Err(_) => return vec![CoreVersion::default()],
+        };
+        let versions = parse_versions(&versions);
+        assert!(
+            !versions.is_empty(),
+            "{} does not list any version",
+            CORE_VERSIONS_ENV
+        );
+
+        versions
+    }
+
+    fn image(&self) -> io::Result<BitcoindImage> {
+        match self {
+            CoreVersion::Image(tag) => Ok(BitcoindImage::with_tag(tag.clone())),
+            CoreVersion::Binary(path) => BitcoindImage::with_binary(path),
+        }
+    }
+}
+
+impl Default for CoreVersion {
+    fn default() -> Self {
+        use testcontainers::Image;
+
+        CoreVersion::Image(BitcoindImage::default().tag())
+    }
+}
+
+/// A path if it contains a `/`, an image tag otherwise.
+impl From<&str> for CoreVersion {
+    fn from(version: &str) -> Self {
+        if version.contains('/') {
+            CoreVersion::Binary(PathBuf::from(version))
+        } else {
+            CoreVersion::Image(version.to_string())
+        }
+    }
+}
+
+impl fmt::Display for CoreVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoreVersion::Image(tag) => write!(f, "{}", tag),
+            CoreVersion::Binary(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+fn parse_versions(versions: &str) -> Vec<CoreVersion> {
+    versions
+        .split(',')
+        .map(str::trim)
+        .filter(|version| !version.is_empty())
+        .map(CoreVersion::from)
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// The error returned by the test body, or the message of the panic of the node startup or
+    /// of the test body
+    Failed(String),
+}
+
+/// The outcome of the test against each version, in the order they were run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub outcomes: Vec<(CoreVersion, Outcome)>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = (&CoreVersion, &str)> {
+        self.outcomes
+            .iter()
+            .filter_map(|(version, outcome)| match outcome {
+                Outcome::Passed => None,
+                Outcome::Failed(error) => Some((version, error.as_str())),
+            })
+    }
+
+    /// # Panics
+    ///
+    /// If the test failed against any version, listing the outcome of every version.
+    pub fn assert_passed(&self) {
+        assert!(
+            self.passed(),
+            "test failed against some versions:\n{}",
+            self
+        );
+    }
+}
+
+/// One line per version, e.g. `0.21.0: passed`.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (version, outcome) in &self.outcomes {
+            match outcome {
+                Outcome::Passed => writeln!(f, "{}: passed", version)?,
+                Outcome::Failed(error) => writeln!(f, "{}: failed: {}", version, error)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Run `test` against a node of each of the `versions`, one after the other.
+///
+/// The node is dropped, and its container removed, once the body is done with it. The outcome of
+/// each version is logged as it is known.
+pub async fn for_each_core_version<'c, I, F, Fut, E>(
+    client: &'c clients::Cli,
+    versions: I,
+    mut test: F,
+) -> Report
+where
+    I: IntoIterator<Item = CoreVersion>,
+    F: FnMut(Bitcoind<'c>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: fmt::Display,
+{
+    let mut outcomes = Vec::new();
+
+    for version in versions {
+        let outcome = match version.image() {
+            Ok(image) => start_and_run(client, image, &mut test).await,
+            Err(e) => Outcome::Failed(format!("failed to start bitcoind: {}", e)),
+        };
+
+        match &outcome {
+            Outcome::Passed => tracing::info!(%version, "passed"),
+            Outcome::Failed(error) => tracing::warn!(%version, %error, "failed"),
+        }
+        outcomes.push((version, outcome));
+    }
+
+    Report { outcomes }
+}
+
+async fn start_and_run<'c, F, Fut, E>(
+    client: &'c clients::Cli,
+    image: BitcoindImage,
+    test: &mut F,
+) -> Outcome
+where
+    F: FnMut(Bitcoind<'c>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: fmt::Display,
+{
+    match panic::catch_unwind(AssertUnwindSafe(|| Bitcoind::with_image(client, image))) {
+        Ok(Ok(bitcoind)) => match AssertUnwindSafe(test(bitcoind)).catch_unwind().await {
+            Ok(Ok(())) => Outcome::Passed,
+            Ok(Err(e)) => Outcome::Failed(e.to_string()),
+            Err(panic) => Outcome::Failed(panic_message(panic)),
+        },
+        Ok(Err(e)) => Outcome::Failed(format!("failed to start bitcoind: {}", e)),
+        Err(panic) => Outcome::Failed(format!(
+            "failed to start bitcoind: {}",
+            panic_message(panic)
+        )),
+    }
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => match panic.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "panicked".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_version_list() {
+        assert_eq!(
+            parse_versions("0.21.0, 22.0,,/opt/bitcoin-25.0/bin/bitcoind"),
+            vec![
+                CoreVersion::Image("0.21.0".to_string()),
+                CoreVersion::Image("22.0".to_string()),
+                CoreVersion::Binary(PathBuf::from("/opt/bitcoin-25.0/bin/bitcoind")),
+            ]
+        );
+    }
+
+    #[test]
+    fn report_failures() {
+        let report = Report {
+            outcomes: vec![
+                (CoreVersion::Image("0.21.0".to_string()), Outcome::Passed),
+                (
+                    CoreVersion::Image("0.20.1".to_string()),
+                    Outcome::Failed("descriptors not supported".to_string()),
+                ),
+            ],
+        };
+
+        assert!(!report.passed());
+        assert_eq!(report.failures().count(), 1);
+        assert_eq!(
+            report.to_string(),
+            "0.21.0: passed\n0.20.1: failed: descriptors not supported\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_binary_fails_its_version() {
+        let tc_client = clients::Cli::default();
+        let versions = vec![CoreVersion::from("bin/bitcoind-missing")];
+
+        let report =
+            for_each_core_version(&tc_client, versions, |_| async { crate::Result::Ok(()) }).await;
+
+        assert!(matches!(
+            &report.outcomes[0].1,
+            Outcome::Failed(message) if message.starts_with("failed to start bitcoind")
+        ));
+    }
+
+    #[cfg(feature = "test-docker")]
+    #[tokio::test]
+    async fn report_outcome_per_version() {
+        use crate::image::RECENT_CORE_TAG;
+        use crate::Client;
+
+        let tc_client = clients::Cli::default();
+        let versions = vec![
+            CoreVersion::default(),
+            CoreVersion::Image(RECENT_CORE_TAG.to_string()),
+        ];
+        let mut expected_versions = vec![210_000, 240_001].into_iter();
+
+        let report = for_each_core_version(&tc_client, versions, |bitcoind| {
+            let expected_version = expected_versions.next();
+            async move {
+                bitcoind.wait_until_ready(crate::READY_TIMEOUT).await?;
+                let client = Client::new(bitcoind.node_url.clone());
+                assert_eq!(Some(client.capabilities().await?.version), expected_version);
+
+                // Only known from bitcoind 22.0
+                client
+                    .call::<serde_json::Value>("getdeploymentinfo", serde_json::Value::Null)
+                    .await?;
+
+                crate::Result::Ok(())
+            }
+        })
+        .await;
+
+        assert!(matches!(
+            &report.outcomes[0].1,
+            Outcome::Failed(message) if !message.contains("assertion")
+        ));
+        assert_eq!(report.outcomes[1].1, Outcome::Passed);
+    }
+}
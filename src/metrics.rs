@@ -0,0 +1,111 @@
+//! Call counts and latency histograms of the RPC calls, per method
+//!
+//! Every call made by a [`Client`](crate::Client), whichever wallet or node it is made to, is
+//! counted in a registry shared by the whole process. Comparing [`snapshot`]s taken before and
+//! after a test setup step shows which calls it made and how long they took.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// Upper bounds of the buckets of the latency histograms
+pub const LATENCY_BUCKETS: [Duration; 10] = [
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+];
+
+static REGISTRY: Mutex<BTreeMap<String, MethodMetrics>> = Mutex::new(BTreeMap::new());
+
+/// The calls made to one RPC method.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MethodMetrics {
+    pub calls: u64,
+    /// Calls which failed with a transport error or an error of the node
+    pub errors: u64,
+    pub total_latency: Duration,
+    pub max_latency: Duration,
+    /// Number of calls per bucket of [`LATENCY_BUCKETS`], the last one counts the calls slower
+    /// than all the buckets.
+    pub latency_histogram: [u64; LATENCY_BUCKETS.len() + 1],
+}
+
+impl MethodMetrics {
+    pub fn mean_latency(&self) -> Option<Duration> {
+        let calls = u32::try_from(self.calls).ok().filter(|calls| *calls > 0)?;
+
+        Some(self.total_latency / calls)
+    }
+
+    fn record(&mut self, latency: Duration, error: bool) {
+        self.calls += 1;
+        if error {
+            self.errors += 1;
+        }
+        self.total_latency += latency;
+        self.max_latency = self.max_latency.max(latency);
+
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| latency <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.latency_histogram[bucket] += 1;
+    }
+}
+
+pub(crate) fn record(method: &str, latency: Duration, error: bool) {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(method.to_string())
+        .or_default()
+        .record(latency, error);
+}
+
+/// The metrics of every method called since the process started or the last [`reset`].
+pub fn snapshot() -> BTreeMap<String, MethodMetrics> {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+pub fn reset() {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clear();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn record_latency_histogram() {
+        record("metrics_test", Duration::from_millis(3), false);
+        record("metrics_test", Duration::from_millis(7), true);
+        record("metrics_test", Duration::from_secs(10), false);
+
+        let metrics = snapshot()["metrics_test"];
+
+        assert_eq!(metrics.calls, 3);
+        assert_eq!(metrics.errors, 1);
+        assert_eq!(metrics.max_latency, Duration::from_secs(10));
+        assert_eq!(metrics.latency_histogram[1], 1);
+        assert_eq!(metrics.latency_histogram[2], 1);
+        assert_eq!(metrics.latency_histogram[LATENCY_BUCKETS.len()], 1);
+        assert_eq!(
+            metrics.mean_latency(),
+            Some(Duration::from_millis(10_010) / 3)
+        );
+    }
+}
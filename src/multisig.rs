@@ -0,0 +1,419 @@
+//! A multisig wallet shared by several cosigner wallets
+//!
+//! The extended key of each cosigner is the account key of its wallet, read with
+//! `listdescriptors`. Nodes older than 23.0 cannot export private descriptors, the keys are then
+//! generated locally and imported in blank cosigner wallets. Each cosigner wallet imports the
+//! multisig descriptor with its own private key, and a watch-only wallet imports it with the
+//! public keys of all cosigners. The watch-only wallet funds transactions while the cosigners
+//! sign them in turn.
+
+use crate::bitcoind_rpc::{self, Client, CreateWalletOptions};
+use crate::bitcoind_rpc_api::{BitcoindRpcApi, ImportDescriptorsRequest, PsbtBase64};
+use crate::capabilities;
+use crate::{Bitcoind, Error, Result, Wallet};
+use bitcoin::secp256k1::rand::{thread_rng, RngCore};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::util::bip32::{DerivationPath, ExtendedPrivKey, ExtendedPubKey};
+use bitcoin::{Address, Amount, Network, Transaction, Txid};
+use bitcoincore_rpc_json::ImportMultiRescanSince;
+use url::Url;
+
+/// x-only key without known discrete logarithm, from BIP 341, used as taproot internal key so
+/// that outputs can only be spent through the multisig script.
+const NUMS_INTERNAL_KEY: &str = "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultisigKind {
+    /// `wsh(sortedmulti(...))`
+    Wsh,
+    /// `tr(<NUMS>,sortedmulti_a(...))`, requires bitcoind 24.0, [`MultisigFixture::new`] fails
+    /// with [`bitcoind_rpc::Error::Unsupported`] on older nodes
+    Taproot,
+}
+
+#[derive(Debug)]
+pub struct MultisigFixture {
+    pub cosigners: Vec<Wallet>,
+    pub watch_only: Wallet,
+    pub threshold: usize,
+    pub kind: MultisigKind,
+    /// Public receive descriptor, with its checksum
+    pub descriptor: String,
+}
+
+impl MultisigFixture {
+    /// Create `n` cosigner wallets and a watch-only wallet for a `threshold`-of-`n` multisig.
+    /// Wallets are named after `name`.
+    pub async fn new(
+        name: &str,
+        url: Url,
+        threshold: usize,
+        n: usize,
+        kind: MultisigKind,
+    ) -> Result<Self> {
+        if threshold == 0 || threshold > n {
+            return Err(Error::InvalidThreshold { threshold, n });
+        }
+
+        let client = Client::new(url.clone());
+        let version = client.capabilities().await?.version;
+        if kind == MultisigKind::Taproot && version < capabilities::V24 {
+            return Err(bitcoind_rpc::Error::Unsupported {
+                feature: "sortedmulti_a",
+                version,
+            }
+            .into());
+        }
+
+        let wallet_names = (0..n)
+            .map(|index| format!("{}_cosigner_{}", name, index))
+            .collect::<Vec<_>>();
+        let keys = if version >= capabilities::V23 {
+            let mut keys = Vec::with_capacity(n);
+            for wallet_name in &wallet_names {
+                client
+                    .create_wallet(
+                        wallet_name,
+                        CreateWalletOptions {
+                            descriptors: Some(true),
+                            ..CreateWalletOptions::default()
+                        },
+                    )
+                    .await?;
+                keys.push(CosignerKey::from_wallet(&client, wallet_name).await?);
+            }
+            keys
+        } else {
+            for wallet_name in &wallet_names {
+                client
+                    .create_wallet(
+                        wallet_name,
+                        CreateWalletOptions {
+                            blank: Some(true),
+                            descriptors: Some(true),
+                            ..CreateWalletOptions::default()
+                        },
+                    )
+                    .await?;
+            }
+            let network = client.network().await?;
+            let secp = Secp256k1::new();
+            (0..n)
+                .map(|_| CosignerKey::generate(&secp, network))
+                .collect::<Result<Vec<_>>>()?
+        };
+        let public_keys = keys
+            .iter()
+            .map(|key| key.public.clone())
+            .collect::<Vec<_>>();
+
+        let mut cosigners = Vec::with_capacity(n);
+        for (index, (wallet_name, key)) in wallet_names.iter().zip(&keys).enumerate() {
+            let mut cosigner_keys = public_keys.clone();
+            cosigner_keys[index] = key.private.clone();
+            import_descriptors(&client, wallet_name, kind, threshold, &cosigner_keys).await?;
+
+            cosigners.push(Wallet::new(wallet_name, url.clone()).await?);
+        }
+
+        let wallet_name = format!("{}_watch_only", name);
+        client
+            .create_wallet(
+                &wallet_name,
+                CreateWalletOptions {
+                    disable_private_keys: Some(true),
+                    blank: Some(true),
+                    descriptors: Some(true),
+                    ..CreateWalletOptions::default()
+                },
+            )
+            .await?;
+        let descriptor =
+            import_descriptors(&client, &wallet_name, kind, threshold, &public_keys).await?;
+        let watch_only = Wallet::new(&wallet_name, url).await?;
+
+        Ok(Self {
+            cosigners,
+            watch_only,
+            threshold,
+            kind,
+            descriptor,
+        })
+    }
+
+    pub async fn new_address(&self) -> Result<Address> {
+        let address_type = match self.kind {
+            MultisigKind::Wsh => "bech32",
+            MultisigKind::Taproot => "bech32m",
+        };
+        let address = self
+            .watch_only
+            .client
+            .with_wallet(self.watch_only.name())?
+            .getnewaddress(None, Some(address_type.into()))
+            .await?;
+
+        Ok(address)
+    }
+
+    /// Send `amount` to a new multisig address and confirm it.
+    pub async fn fund(&self, bitcoind: &Bitcoind<'_>, amount: Amount) -> Result<Address> {
+        let address = self.new_address().await?;
+        bitcoind.mint(address.clone(), amount).await?;
+
+        Ok(address)
+    }
+
+    /// Sign the PSBT with `threshold` cosigners, one after the other, and finalize it.
+    pub async fn sign_psbt(&self, psbt: PsbtBase64) -> Result<Transaction> {
+        let mut psbt = psbt;
+        for cosigner in self.cosigners.iter().take(self.threshold) {
+            psbt = cosigner.wallet_process_psbt(psbt).await?.into();
+        }
+
+        let finalized = self.watch_only.finalize_psbt(psbt).await?;
+        let transaction = finalized
+            .transaction()
+            .ok_or(Error::IncompletePsbt)?
+            .map_err(crate::bitcoind_rpc::Error::from)?;
+
+        Ok(transaction)
+    }
+
+    /// Fund a transaction paying `amount` to `address` from the multisig, sign and broadcast it.
+    pub async fn spend(&self, address: Address, amount: Amount) -> Result<Txid> {
+        let psbt = self.watch_only.fund_psbt(address, amount).await?;
+        let transaction = self.sign_psbt(psbt.into()).await?;
+        let txid = self.watch_only.send_raw_transaction(transaction).await?;
+
+        Ok(txid)
+    }
+}
+
+/// The private and public forms of a cosigner key, with their origin, as used in descriptors.
+struct CosignerKey {
+    private: String,
+    public: String,
+}
+
+impl CosignerKey {
+    /// The account key of the active receive `wpkh` descriptor of the wallet, requires
+    /// bitcoind 23.0.
+    async fn from_wallet(client: &Client, wallet_name: &str) -> Result<Self> {
+        let not_found = || Error::CosignerKeyNotFound(wallet_name.to_string());
+
+        let private = client
+            .with_wallet(wallet_name)?
+            .listdescriptors(Some(true))
+            .await?
+            .descriptors
+            .iter()
+            .filter(|descriptor| descriptor.active && descriptor.internal != Some(true))
+            .find_map(|descriptor| account_key(&descriptor.desc))
+            .ok_or_else(not_found)?;
+        let public = client
+            .getdescriptorinfo(&format!("wpkh({}/0/*)", private))
+            .await?
+            .descriptor;
+        let public = account_key(&public).ok_or_else(not_found)?;
+
+        Ok(Self { private, public })
+    }
+
+    fn generate<C>(secp: &Secp256k1<C>, network: Network) -> Result<Self>
+    where
+        C: bitcoin::secp256k1::Signing,
+    {
+        let mut seed = [0u8; 32];
+        thread_rng().fill_bytes(&mut seed);
+
+        // BIP 87 account path
+        let coin_type = match network {
+            Network::Bitcoin => 0,
+            _ => 1,
+        };
+        let path = format!("87h/{}h/0h", coin_type);
+
+        let master = ExtendedPrivKey::new_master(network, &seed)?;
+        let account =
+            master.derive_priv(secp, &format!("m/{}", path).parse::<DerivationPath>()?)?;
+        let origin = format!("[{}/{}]", master.fingerprint(secp), path);
+
+        Ok(Self {
+            private: format!("{}{}", origin, account),
+            public: format!("{}{}", origin, ExtendedPubKey::from_priv(secp, &account)),
+        })
+    }
+}
+
+/// The key of a `wpkh(<key>/0/*)` descriptor, with its origin.
+fn account_key(descriptor: &str) -> Option<String> {
+    descriptor
+        .split('#')
+        .next()?
+        .strip_prefix("wpkh(")?
+        .strip_suffix("/0/*)")
+        .map(str::to_string)
+}
+
+/// Import the receive and change multisig descriptors as active descriptors of the wallet,
+/// returns the receive descriptor.
+async fn import_descriptors(
+    client: &Client,
+    wallet_name: &str,
+    kind: MultisigKind,
+    threshold: usize,
+    keys: &[String],
+) -> Result<String> {
+    let mut requests = Vec::new();
+    let mut descriptors = Vec::new();
+
+    for internal in [false, true] {
+        let branch = if internal { 1 } else { 0 };
+        let keys = keys
+            .iter()
+            .map(|key| format!("{}/{}/*", key, branch))
+            .collect::<Vec<_>>()
+            .join(",");
+        let descriptor = match kind {
+            MultisigKind::Wsh => format!("wsh(sortedmulti({},{}))", threshold, keys),
+            MultisigKind::Taproot => format!(
+                "tr({},sortedmulti_a({},{}))",
+                NUMS_INTERNAL_KEY, threshold, keys
+            ),
+        };
+        let checksum = client.getdescriptorinfo(&descriptor).await?.checksum;
+        let descriptor = format!("{}#{}", descriptor, checksum);
+
+        requests.push(ImportDescriptorsRequest {
+            desc: descriptor.clone(),
+            timestamp: ImportMultiRescanSince::Now,
+            active: Some(true),
+            range: None,
+            next_index: None,
+            internal: Some(internal),
+            label: None,
+        });
+        descriptors.push(descriptor);
+    }
+
+    let results = client
+        .with_wallet(wallet_name)?
+        .importdescriptors(requests)
+        .await?;
+    if let Some(error) = results.into_iter().find_map(|result| result.error) {
+        return Err(Error::ImportDescriptors(error.message));
+    }
+
+    Ok(descriptors.swap_remove(0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "test-docker")]
+    #[tokio::test]
+    async fn two_of_three_wsh_multisig_spend() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind = Bitcoind::new(&tc_client).unwrap();
+        bitcoind.init(5).await.unwrap();
+
+        let multisig =
+            MultisigFixture::new("escrow", bitcoind.node_url.clone(), 2, 3, MultisigKind::Wsh)
+                .await
+                .unwrap();
+        let amount = Amount::from_btc(2.0).unwrap();
+        multisig.fund(&bitcoind, amount).await.unwrap();
+        assert_eq!(multisig.watch_only.balance().await.unwrap(), amount);
+
+        let address = Wallet::new(&bitcoind.wallet_name, bitcoind.node_url.clone())
+            .await
+            .unwrap()
+            .new_address()
+            .await
+            .unwrap();
+        let _txid = multisig
+            .spend(address, Amount::from_btc(1.0).unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[cfg(feature = "test-docker")]
+    #[tokio::test]
+    async fn two_of_two_taproot_multisig_spend_with_wallet_keys() {
+        use crate::image::{BitcoindImage, RECENT_CORE_TAG};
+
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind =
+            Bitcoind::with_image(&tc_client, BitcoindImage::with_tag(RECENT_CORE_TAG)).unwrap();
+        bitcoind.init(5).await.unwrap();
+
+        let multisig = MultisigFixture::new(
+            "escrow",
+            bitcoind.node_url.clone(),
+            2,
+            2,
+            MultisigKind::Taproot,
+        )
+        .await
+        .unwrap();
+        let wallet_key = account_key(
+            &multisig.cosigners[0]
+                .client
+                .with_wallet(multisig.cosigners[0].name())
+                .unwrap()
+                .listdescriptors(None)
+                .await
+                .unwrap()
+                .descriptors
+                .iter()
+                .find(|descriptor| descriptor.desc.starts_with("wpkh("))
+                .unwrap()
+                .desc,
+        )
+        .unwrap();
+        assert!(multisig.descriptor.contains(&wallet_key));
+
+        let amount = Amount::from_btc(2.0).unwrap();
+        multisig.fund(&bitcoind, amount).await.unwrap();
+        let address = Wallet::new(&bitcoind.wallet_name, bitcoind.node_url.clone())
+            .await
+            .unwrap()
+            .new_address()
+            .await
+            .unwrap();
+        let _txid = multisig
+            .spend(address, Amount::from_btc(1.0).unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn reject_invalid_threshold() {
+        let res = futures::executor::block_on(MultisigFixture::new(
+            "escrow",
+            "http://localhost:1".parse().unwrap(),
+            3,
+            2,
+            MultisigKind::Wsh,
+        ));
+
+        assert!(matches!(
+            res,
+            Err(Error::InvalidThreshold { threshold: 3, n: 2 })
+        ));
+    }
+
+    #[test]
+    fn account_key_of_wpkh_descriptor() {
+        let key = "[d34db33f/84h/1h/0h]tpubDCyvm9vbeYqZKLHyAVBy6ptFE2VzyGzUmvB5BkhdNBRLy6oXUF5mtyE5y8ZQ89aRbkiM7onJCoz7MaL2Hyw2yVAPZ3tNDHBt7ggqBgK6VCM";
+
+        assert_eq!(
+            account_key(&format!("wpkh({}/0/*)#2xmvdu2q", key)),
+            Some(key.to_string())
+        );
+        assert_eq!(account_key(&format!("wpkh({}/1/*)", key)), None);
+        assert_eq!(account_key(&format!("tr({}/0/*)", key)), None);
+    }
+}
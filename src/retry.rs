@@ -0,0 +1,129 @@
+//! Retry of RPC calls with exponential backoff
+//!
+//! A node which is starting, or restarting, refuses connections and then answers with the
+//! "warming up" error until it has loaded its block index and wallets. Such calls are retried
+//! after a delay doubling with each attempt. Calls which time out are not retried since the node
+//! may have handled them.
+
+use std::time::Duration;
+
+/// How many times and how long to wait before retrying a call failing to connect or because the
+/// node is warming up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each following one
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// The delay before retrying the call which failed `attempt` times, `None` once all
+    /// retries are exhausted.
+    pub fn backoff(&self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+
+        let backoff = 2u32
+            .checked_pow(attempt)
+            .and_then(|factor| self.initial_backoff.checked_mul(factor))
+            .unwrap_or(self.max_backoff);
+
+        Some(backoff.min(self.max_backoff))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BitcoindRpcApi, Client};
+    use std::time::Instant;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+
+        let backoffs = (0..6)
+            .map(|attempt| policy.backoff(attempt))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            backoffs,
+            vec![
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(200)),
+                Some(Duration::from_millis(400)),
+                Some(Duration::from_millis(500)),
+                Some(Duration::from_millis(500)),
+                None,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_refused_connections() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+        };
+        let client = Client::builder("http://localhost:1".parse().unwrap())
+            .retry(policy)
+            .build()
+            .unwrap();
+
+        let start = Instant::now();
+        let res = client.getblockcount().await;
+
+        assert!(res.is_err());
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[cfg(feature = "mock")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn timed_out_send_is_not_sent_again() {
+        use crate::mock::MockBitcoind;
+        use crate::Wallet;
+
+        let bitcoind = MockBitcoind::new().unwrap();
+        let client = Client::builder(bitcoind.node_url.clone())
+            .timeout(Duration::from_millis(100))
+            .retry(RetryPolicy::default())
+            .build()
+            .unwrap();
+        let wallet = Wallet::with_client("wallet", client).await.unwrap();
+        let address = wallet.new_address().await.unwrap();
+        bitcoind.script("sendtoaddress", |_| {
+            std::thread::sleep(Duration::from_millis(300));
+            Ok(serde_json::json!("00".repeat(32)))
+        });
+
+        let res = wallet
+            .send_to_address(address, bitcoin::Amount::from_sat(10_000))
+            .await;
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert!(res.is_err());
+        let sends = bitcoind
+            .calls()
+            .into_iter()
+            .filter(|call| call.method == "sendtoaddress")
+            .count();
+        assert_eq!(sends, 1);
+    }
+}
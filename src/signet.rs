@@ -0,0 +1,217 @@
+//! A private signet whose blocks are signed with a locally generated challenge key
+//!
+//! Blocks are assembled from `getblocktemplate`, signed following
+//! [BIP 325](https://github.com/bitcoin/bips/blob/master/bip-0325.mediawiki) and their proof of
+//! work is ground locally before being submitted. Signet blocks have a fixed minimum difficulty,
+//! grinding a block hence takes a few million hashes.
+
+use crate::bitcoind_rpc::Client;
+use crate::block_builder::{grind_nonce, submit_block, BlockBuilder};
+use crate::Result;
+use bitcoin::blockdata::opcodes::all::{OP_CHECKSIG, OP_PUSHBYTES_0, OP_RETURN};
+use bitcoin::blockdata::script::Builder;
+use bitcoin::consensus::encode::serialize;
+use bitcoin::secp256k1::{rand, Message, Secp256k1, SecretKey};
+use bitcoin::{
+    Address, Block, BlockHash, EcdsaSig, EcdsaSighashType, OutPoint, PackedLockTime, PublicKey,
+    Script, Sequence, Transaction, TxIn, TxMerkleNode, TxOut, Witness,
+};
+
+/// Identifies the signet solution within the witness commitment of the coinbase.
+const SIGNET_HEADER: [u8; 4] = [0xec, 0xc7, 0xda, 0xa2];
+
+/// Mines blocks on a private signet by signing them with its challenge key.
+#[derive(Debug, Clone)]
+pub struct Miner {
+    secret_key: SecretKey,
+    challenge: Script,
+}
+
+impl Miner {
+    /// Generates a new challenge key, the challenge is a `<pubkey> OP_CHECKSIG` script.
+    pub fn new() -> Self {
+        let secp = Secp256k1::signing_only();
+        let secret_key = SecretKey::new(&mut rand::thread_rng());
+        let public_key = PublicKey::new(secret_key.public_key(&secp));
+        let challenge = Builder::new()
+            .push_key(&public_key)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+
+        Self {
+            secret_key,
+            challenge,
+        }
+    }
+
+    /// The script every block of the signet must satisfy, to pass as `-signetchallenge`.
+    pub fn challenge(&self) -> &Script {
+        &self.challenge
+    }
+
+    /// Mines `nblocks` blocks paying their reward to `address`, signet counterpart of
+    /// `generatetoaddress`.
+    pub async fn generate_to_address(
+        &self,
+        client: &Client,
+        nblocks: u32,
+        address: &Address,
+    ) -> Result<Vec<BlockHash>> {
+        let mut block_hashes = Vec::new();
+
+        for _ in 0..nblocks {
+            let block = BlockBuilder::from_node(client, address.clone())
+                .await?
+                .assemble()?;
+            let miner = self.clone();
+            let block = tokio::task::spawn_blocking(move || miner.sign_and_grind(block))
+                .await
+                .expect("grinding does not panic");
+
+            block_hashes.push(submit_block(client, &block).await?);
+        }
+
+        Ok(block_hashes)
+    }
+
+    /// Signs the block and grinds its proof of work. The signature commits to the time of the
+    /// block, hence the block is signed again whenever the time is bumped because all nonces are
+    /// exhausted.
+    fn sign_and_grind(&self, mut block: Block) -> Block {
+        loop {
+            let mut signed = self.sign(block.clone());
+            match grind_nonce(&signed.header) {
+                Some(nonce) => {
+                    signed.header.nonce = nonce;
+                    return signed;
+                }
+                None => block.header.time += 1,
+            }
+        }
+    }
+
+    /// Adds the signet solution to the witness commitment of the coinbase.
+    fn sign(&self, mut block: Block) -> Block {
+        let commitment = witness_commitment_output(&mut block).script_pubkey.clone();
+        let with_commitment_section = |data: &[u8]| {
+            Builder::from(commitment.to_bytes())
+                .push_slice(data)
+                .into_script()
+        };
+
+        // The signed data commits to the coinbase with the signet header but without the solution
+        witness_commitment_output(&mut block).script_pubkey =
+            with_commitment_section(&SIGNET_HEADER);
+        let to_spend = Transaction {
+            version: 0,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Builder::new()
+                    .push_opcode(OP_PUSHBYTES_0)
+                    .push_slice(&signed_block_data(&block))
+                    .into_script(),
+                sequence: Sequence(0),
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 0,
+                script_pubkey: self.challenge.clone(),
+            }],
+        };
+        let to_sign = Transaction {
+            version: 0,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(to_spend.txid(), 0),
+                script_sig: Script::new(),
+                sequence: Sequence(0),
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 0,
+                script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script(),
+            }],
+        };
+
+        let sighash = to_sign.signature_hash(0, &self.challenge, EcdsaSighashType::All.to_u32());
+        let message = Message::from_slice(&sighash[..]).expect("sighash is 32 bytes");
+        let signature = Secp256k1::signing_only().sign_ecdsa(&message, &self.secret_key);
+
+        let script_sig = Builder::new()
+            .push_slice(&EcdsaSig::sighash_all(signature).to_vec())
+            .into_script();
+        let mut solution = SIGNET_HEADER.to_vec();
+        solution.extend(serialize(&script_sig));
+        solution.extend(serialize(&Witness::new()));
+
+        witness_commitment_output(&mut block).script_pubkey = with_commitment_section(&solution);
+        block.header.merkle_root = merkle_root(&block);
+
+        block
+    }
+}
+
+impl Default for Miner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The block data committed to by the signet solution: version, previous block hash, merkle
+/// root and time.
+fn signed_block_data(block: &Block) -> Vec<u8> {
+    let mut data = serialize(&block.header.version);
+    data.extend(serialize(&block.header.prev_blockhash));
+    data.extend(serialize(&merkle_root(block)));
+    data.extend(serialize(&block.header.time));
+
+    data
+}
+
+/// The witness commitment is the last output of the coinbase starting with its header.
+fn witness_commitment_output(block: &mut Block) -> &mut TxOut {
+    const WITNESS_COMMITMENT_HEADER: [u8; 6] = [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+
+    block.txdata[0]
+        .output
+        .iter_mut()
+        .rev()
+        .find(|output| {
+            output
+                .script_pubkey
+                .as_bytes()
+                .starts_with(&WITNESS_COMMITMENT_HEADER)
+        })
+        .expect("templates of signets commit to witnesses")
+}
+
+fn merkle_root(block: &Block) -> TxMerkleNode {
+    block
+        .compute_merkle_root()
+        .expect("block has at least a coinbase")
+}
+
+#[cfg(all(test, feature = "test-docker"))]
+mod test {
+    use crate::{Bitcoind, BitcoindRpcApi, Client, Wallet};
+    use bitcoin::Network;
+
+    #[tokio::test]
+    async fn mine_signed_signet_blocks() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind = Bitcoind::new_signet(&tc_client).unwrap();
+        let client = Client::new(bitcoind.node_url.clone());
+
+        assert_eq!(client.network().await.unwrap(), Network::Signet);
+
+        let wallet = Wallet::new("miner", bitcoind.node_url.clone())
+            .await
+            .unwrap();
+        let address = wallet.new_address().await.unwrap();
+        let block_hashes = bitcoind.generate(2, address).await.unwrap();
+
+        assert_eq!(block_hashes.len(), 2);
+        assert_eq!(client.getblockcount().await.unwrap(), 2);
+    }
+}
@@ -1,11 +1,24 @@
-use crate::bitcoind_rpc::{Client, Result};
-use crate::bitcoind_rpc_api::{BitcoindRpcApi, PsbtBase64, WalletProcessPsbtResponse};
+use crate::bitcoind_rpc::{Client, CreateWalletOptions, Error, Result};
+use crate::bitcoind_rpc_api::{
+    BitcoindRpcApi, ListUnspentQueryOptions, MigrateWalletResponse, PsbtBase64,
+    WalletProcessPsbtResponse,
+};
+use crate::capabilities;
 use bitcoin::hashes::hex::FromHex;
-use bitcoin::{Address, Amount, Transaction, Txid};
+use bitcoin::util::misc::MessageSignature;
+use bitcoin::{
+    Address, Amount, BlockHash, OutPoint, PackedLockTime, Script, Transaction, TxIn, TxOut, Txid,
+    Witness,
+};
 use bitcoincore_rpc_json::{
     FinalizePsbtResult, GetAddressInfoResult, GetTransactionResult, GetWalletInfoResult,
+    ListReceivedByAddressResult, ListSinceBlockResult, ListTransactionResult,
     ListUnspentResultEntry,
 };
+use std::convert::TryFrom;
+use std::path::Path;
+use std::time::Duration;
+use tokio::runtime::{Handle, RuntimeFlavor};
 use url::Url;
 
 /// A wrapper to bitcoind wallet
@@ -18,7 +31,34 @@ pub struct Wallet {
 impl Wallet {
     /// Create a wallet on the bitcoind instance or use the wallet with the same name
     /// if it exists.
+    ///
+    /// The wallet is a descriptor wallet from bitcoind 23.0, legacy only RPCs such as
+    /// `sethdseed` fail on it.
     pub async fn new(name: &str, url: Url) -> Result<Self> {
+        Self::with_passphrase(name, url, None).await
+    }
+
+    /// Create a wallet encrypted with `passphrase` on the bitcoind instance or use the
+    /// wallet with the same name if it exists, failing with [`Error::WalletNotEncrypted`] if
+    /// that wallet is not encrypted. The wallet is locked once created.
+    pub async fn new_encrypted(name: &str, url: Url, passphrase: &str) -> Result<Self> {
+        Self::with_passphrase(name, url, Some(passphrase.to_string())).await
+    }
+
+    /// Create a wallet, or use the existing one, through `client`, a client built with
+    /// [`Client::builder`] for instance.
+    pub async fn with_client(name: &str, client: Client) -> Result<Self> {
+        let wallet = Self {
+            name: name.to_string(),
+            client,
+        };
+
+        wallet.init(None).await?;
+
+        Ok(wallet)
+    }
+
+    async fn with_passphrase(name: &str, url: Url, passphrase: Option<String>) -> Result<Self> {
         let client = Client::new(url);
 
         let wallet = Self {
@@ -26,27 +66,133 @@ impl Wallet {
             client,
         };
 
-        wallet.init().await?;
+        wallet.init(passphrase).await?;
 
         Ok(wallet)
     }
 
-    async fn init(&self) -> Result<()> {
+    /// Restore a wallet named `name` from a backup file on the bitcoind instance.
+    ///
+    /// Requires bitcoind 23.0, use `Bitcoind::copy_to_container` to restore a backup from the
+    /// host.
+    pub async fn restore(name: &str, url: Url, backup_file: &Path) -> Result<Self> {
+        let client = Client::new(url);
+        client
+            .require_version("restorewallet", capabilities::V23)
+            .await?;
+        client.restorewallet(name, backup_file, None).await?;
+
+        Ok(Self {
+            name: name.to_string(),
+            client,
+        })
+    }
+
+    async fn init(&self, passphrase: Option<String>) -> Result<()> {
         match self.info().await {
             Err(_) => {
                 self.client
-                    .createwallet(&self.name, None, None, None, None)
+                    .create_wallet(
+                        &self.name,
+                        CreateWalletOptions {
+                            passphrase,
+                            ..CreateWalletOptions::default()
+                        },
+                    )
                     .await?;
                 Ok(())
             }
+            Ok(info) if passphrase.is_some() && info.unlocked_until.is_none() => {
+                Err(Error::WalletNotEncrypted(self.name.clone()))
+            }
             Ok(_) => Ok(()),
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub async fn info(&self) -> Result<GetWalletInfoResult> {
         Ok(self.client.with_wallet(&self.name)?.getwalletinfo().await?)
     }
 
+    /// Encrypt the wallet with `passphrase`, the wallet is locked afterwards.
+    pub async fn encrypt(&self, passphrase: &str) -> Result<()> {
+        self.client
+            .with_wallet(&self.name)?
+            .encryptwallet(passphrase)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Unlock the wallet for at most `timeout`, the wallet is locked again when the returned
+    /// guard is dropped.
+    pub async fn unlock(&self, passphrase: &str, timeout: Duration) -> Result<UnlockGuard> {
+        let client = self.client.with_wallet(&self.name)?;
+        client
+            .walletpassphrase(passphrase, timeout.as_secs().max(1))
+            .await?;
+
+        Ok(UnlockGuard {
+            client: Some(client),
+        })
+    }
+
+    pub async fn lock(&self) -> Result<()> {
+        self.client.with_wallet(&self.name)?.walletlock().await?;
+
+        Ok(())
+    }
+
+    pub async fn change_passphrase(
+        &self,
+        old_passphrase: &str,
+        new_passphrase: &str,
+    ) -> Result<()> {
+        self.client
+            .with_wallet(&self.name)?
+            .walletpassphrasechange(old_passphrase, new_passphrase)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Back the wallet up to `destination`, a path on the bitcoind instance.
+    ///
+    /// Use `Bitcoind::copy_from_container` to retrieve the backup on the host.
+    pub async fn backup(&self, destination: &Path) -> Result<()> {
+        self.client
+            .with_wallet(&self.name)?
+            .backupwallet(destination)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn unload(&self) -> Result<()> {
+        self.client.unloadwallet(Some(&self.name), None).await?;
+
+        Ok(())
+    }
+
+    pub async fn load(&self) -> Result<()> {
+        self.client.loadwallet(&self.name, None).await?;
+
+        Ok(())
+    }
+
+    /// Migrate a legacy wallet to a descriptor wallet, requires bitcoind 24.0.
+    pub async fn migrate(&self) -> Result<MigrateWalletResponse> {
+        self.client
+            .require_version("migratewallet", capabilities::V24)
+            .await?;
+        let res = self.client.with_wallet(&self.name)?.migratewallet().await?;
+
+        Ok(res)
+    }
+
     pub async fn median_time(&self) -> Result<u64> {
         self.client.median_time().await
     }
@@ -69,21 +215,105 @@ impl Wallet {
             .await?)
     }
 
+    pub async fn new_address_with_label(&self, label: &str) -> Result<Address> {
+        Ok(self
+            .client
+            .with_wallet(&self.name)?
+            .getnewaddress(Some(label.into()), Some("bech32".into()))
+            .await?)
+    }
+
+    pub async fn set_label(&self, address: &Address, label: &str) -> Result<()> {
+        self.client.validate_network(address).await?;
+        self.client
+            .with_wallet(&self.name)?
+            .setlabel(address, label)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn addresses_by_label(&self, label: &str) -> Result<Vec<Address>> {
+        let addresses = self
+            .client
+            .with_wallet(&self.name)?
+            .getaddressesbylabel(label)
+            .await?;
+
+        Ok(addresses.into_keys().collect())
+    }
+
+    pub async fn labels(&self) -> Result<Vec<String>> {
+        Ok(self
+            .client
+            .with_wallet(&self.name)?
+            .listlabels(None)
+            .await?)
+    }
+
+    /// Total amount received by an address of the wallet in confirmed transactions.
+    pub async fn received_by_address(&self, address: &Address) -> Result<Amount> {
+        self.client.validate_network(address).await?;
+        let received = self
+            .client
+            .with_wallet(&self.name)?
+            .getreceivedbyaddress(address, None)
+            .await?;
+
+        Ok(received.into())
+    }
+
+    /// Total amount received by the addresses with `label` in confirmed transactions.
+    pub async fn received_by_label(&self, label: &str) -> Result<Amount> {
+        let received = self
+            .client
+            .with_wallet(&self.name)?
+            .getreceivedbylabel(label, None)
+            .await?;
+
+        Ok(received.into())
+    }
+
+    /// Amounts received per address in confirmed transactions.
+    pub async fn list_received_by_address(&self) -> Result<Vec<ListReceivedByAddressResult>> {
+        Ok(self
+            .client
+            .with_wallet(&self.name)?
+            .listreceivedbyaddress(None, None, None, None)
+            .await?)
+    }
+
     pub async fn balance(&self) -> Result<Amount> {
-        let response = self
+        let balance = self
             .client
             .with_wallet(&self.name)?
             .getbalance(None, None, None)
             .await?;
-        let amount = Amount::from_btc(response)?;
-        Ok(amount)
+        Ok(balance.into())
     }
 
     pub async fn send_to_address(&self, address: Address, amount: Amount) -> Result<Txid> {
+        self.send_to_address_with(address, amount, SendOptions::default())
+            .await
+    }
+
+    pub async fn send_to_address_with(
+        &self,
+        address: Address,
+        amount: Amount,
+        options: SendOptions,
+    ) -> Result<Txid> {
+        self.client.validate_network(&address).await?;
+
         let txid = self
             .client
             .with_wallet(&self.name)?
-            .sendtoaddress(address, amount.to_btc())
+            .sendtoaddress(
+                address,
+                amount.into(),
+                options.replaceable,
+                options.fee_rate,
+            )
             .await?;
         let txid = Txid::from_hex(&txid)?;
 
@@ -100,6 +330,97 @@ impl Wallet {
         Ok(txid)
     }
 
+    /// Broadcast a transaction spending the inputs of the wallet transaction `txid` to
+    /// `destination`, paying `fee_rate` sat/vB.
+    ///
+    /// The node only accepts the conflict in its mempool if it replaces the original
+    /// transaction following BIP 125, see [`crate::double_spend::partitioned`] to broadcast
+    /// conflicts to different nodes instead.
+    pub async fn double_spend(
+        &self,
+        txid: Txid,
+        destination: &Address,
+        fee_rate: u64,
+    ) -> Result<Txid> {
+        let original = self.get_wallet_transaction(txid).await?.transaction()?;
+        let conflict = self
+            .build_double_spend(&original, destination, fee_rate)
+            .await?;
+
+        self.send_raw_transaction(conflict).await
+    }
+
+    /// Build and sign, without broadcasting it, a transaction spending the inputs of
+    /// `original` to `destination`, paying `fee_rate` sat/vB. The inputs must belong to the
+    /// wallet.
+    pub async fn build_double_spend(
+        &self,
+        original: &Transaction,
+        destination: &Address,
+        fee_rate: u64,
+    ) -> Result<Transaction> {
+        self.client.validate_network(destination).await?;
+
+        let mut input_value = Amount::ZERO;
+        for input in original.input.iter() {
+            let previous = self
+                .get_wallet_transaction(input.previous_output.txid)
+                .await?
+                .transaction()?;
+            let vout = usize::try_from(input.previous_output.vout)
+                .map_err(|_| Error::UnexpectedResponse)?;
+            let output = previous.output.get(vout).ok_or(Error::UnexpectedResponse)?;
+            input_value += Amount::from_sat(output.value);
+        }
+
+        let unsigned = |vsize: usize| -> Result<Transaction> {
+            let vsize = u64::try_from(vsize).map_err(|_| Error::UnexpectedResponse)?;
+            let fee = Amount::from_sat(fee_rate * vsize);
+            let value = input_value
+                .checked_sub(fee)
+                .ok_or(Error::FeeExceedsInputs)?;
+
+            Ok(Transaction {
+                version: 2,
+                lock_time: PackedLockTime::ZERO,
+                input: original
+                    .input
+                    .iter()
+                    .map(|input| TxIn {
+                        previous_output: input.previous_output,
+                        script_sig: Script::new(),
+                        sequence: input.sequence,
+                        witness: Witness::new(),
+                    })
+                    .collect(),
+                output: vec![TxOut {
+                    value: value.to_sat(),
+                    script_pubkey: destination.script_pubkey(),
+                }],
+            })
+        };
+
+        // The size of the original transaction is a first estimate, the fee is then adjusted
+        // to the size of the signed conflict.
+        let conflict = self
+            .sign_raw_transaction(unsigned(original.vsize())?)
+            .await?;
+        self.sign_raw_transaction(unsigned(conflict.vsize())?).await
+    }
+
+    pub async fn sign_raw_transaction(&self, transaction: Transaction) -> Result<Transaction> {
+        let signed = self
+            .client
+            .with_wallet(&self.name)?
+            .signrawtransactionwithwallet(transaction.into())
+            .await?;
+        if !signed.complete {
+            return Err(Error::IncompleteSignature);
+        }
+
+        Ok(signed.transaction()?)
+    }
+
     pub async fn get_raw_transaction(&self, txid: Txid) -> Result<Transaction> {
         self.client.get_raw_transaction(txid).await
     }
@@ -114,19 +435,99 @@ impl Wallet {
         Ok(res)
     }
 
+    /// The `count` most recent wallet transactions after skipping the `skip` most recent ones,
+    /// ordered from the oldest to the most recent.
+    pub async fn transactions(&self, count: u32, skip: u32) -> Result<Vec<ListTransactionResult>> {
+        let res = self
+            .client
+            .with_wallet(&self.name)?
+            .listtransactions(None, Some(count), Some(skip), None)
+            .await?;
+
+        Ok(res)
+    }
+
+    /// The wallet transactions in blocks after `block_hash`, or in all blocks if `None`, and in
+    /// the mempool. Transactions of blocks removed by a reorganisation since `block_hash` are
+    /// listed in `removed`.
+    pub async fn since_block(&self, block_hash: Option<BlockHash>) -> Result<ListSinceBlockResult> {
+        let res = self
+            .client
+            .with_wallet(&self.name)?
+            .listsinceblock(block_hash.as_ref(), None, None, Some(true))
+            .await?;
+
+        Ok(res)
+    }
+
     pub async fn address_info(&self, address: &Address) -> Result<GetAddressInfoResult> {
         self.client.address_info(&self.name, address).await
     }
 
     pub async fn list_unspent(&self) -> Result<Vec<ListUnspentResultEntry>> {
+        self.list_unspent_with(ListUnspentQuery::default()).await
+    }
+
+    pub async fn list_unspent_with(
+        &self,
+        query: ListUnspentQuery,
+    ) -> Result<Vec<ListUnspentResultEntry>> {
+        let query_options = ListUnspentQueryOptions {
+            minimum_amount: query.minimum_amount,
+            maximum_amount: query.maximum_amount,
+        };
         let unspents = self
             .client
             .with_wallet(&self.name)?
-            .listunspent(None, None, None, None)
+            .listunspent(
+                query.minconf,
+                query.maxconf,
+                query.addresses,
+                query.include_unsafe,
+                Some(query_options),
+            )
             .await?;
         Ok(unspents)
     }
 
+    /// Lock outputs so that they are not selected when funding transactions, until they are
+    /// unlocked or the node restarts.
+    pub async fn lock_unspent(&self, outpoints: &[OutPoint]) -> Result<()> {
+        self.client
+            .with_wallet(&self.name)?
+            .lockunspent(
+                false,
+                Some(outpoints.iter().copied().map(Into::into).collect()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn unlock_unspent(&self, outpoints: &[OutPoint]) -> Result<()> {
+        self.client
+            .with_wallet(&self.name)?
+            .lockunspent(
+                true,
+                Some(outpoints.iter().copied().map(Into::into).collect()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn list_locked(&self) -> Result<Vec<OutPoint>> {
+        let locked = self
+            .client
+            .with_wallet(&self.name)?
+            .listlockunspent()
+            .await?;
+        Ok(locked.into_iter().map(Into::into).collect())
+    }
+
+    /// Sign `message` with the private key of `address`, which must be a legacy address.
+    pub async fn sign_message(&self, address: &Address, message: &str) -> Result<MessageSignature> {
+        self.client.sign_message(&self.name, address, message).await
+    }
+
     pub async fn fund_psbt(&self, address: Address, amount: Amount) -> Result<String> {
         self.client
             .fund_psbt(&self.name, &[], address, amount)
@@ -161,13 +562,88 @@ impl Wallet {
     }
 }
 
-#[cfg(test)]
+/// Filters of `listunspent`, the defaults of bitcoind apply to the fields left to `None`.
+#[derive(Debug, Clone, Default)]
+pub struct ListUnspentQuery {
+    pub minconf: Option<u32>,
+    pub maxconf: Option<u32>,
+    pub addresses: Option<Vec<Address>>,
+    pub include_unsafe: Option<bool>,
+    pub minimum_amount: Option<Amount>,
+    pub maximum_amount: Option<Amount>,
+}
+
+/// Options of `sendtoaddress`, the defaults of bitcoind apply to the fields left to `None`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SendOptions {
+    /// Signal BIP 125 replaceability
+    pub replaceable: Option<bool>,
+    /// Fee rate in sat/vB
+    pub fee_rate: Option<u64>,
+}
+
+/// Keeps a wallet unlocked until dropped.
+///
+/// [`UnlockGuard::lock`] locks the wallet and reports a failure to do so. Dropping the guard
+/// locks the wallet before returning on a multi-thread tokio runtime. On a current-thread runtime
+/// it can only spawn a task to lock it, which may never run when the runtime is shutting down,
+/// and outside of a runtime the wallet is not locked at all: the wallet then stays unlocked until
+/// the timeout passed to [`Wallet::unlock`] expires. Failures on drop are only logged.
+#[derive(Debug)]
+#[must_use = "the wallet is locked again when the guard is dropped"]
+pub struct UnlockGuard {
+    client: Option<Client>,
+}
+
+impl UnlockGuard {
+    pub async fn lock(mut self) -> Result<()> {
+        if let Some(client) = self.client.take() {
+            client.walletlock().await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for UnlockGuard {
+    fn drop(&mut self) {
+        let client = match self.client.take() {
+            Some(client) => client,
+            None => return,
+        };
+        let lock = async move {
+            if let Err(error) = client.walletlock().await {
+                tracing::warn!(%error, "failed to lock the wallet");
+            }
+        };
+
+        match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(|| handle.block_on(lock))
+            }
+            Ok(handle) => {
+                handle.spawn(lock);
+            }
+            Err(_) => {
+                tracing::warn!(
+                    "unlock guard dropped outside of a tokio runtime, wallet left unlocked"
+                )
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-docker"))]
 mod test {
     use std::time::Duration;
 
-    use crate::{Bitcoind, Wallet};
+    use crate::bitcoind_rpc::{CreateWalletOptions, Error};
+    use crate::image::{BitcoindImage, RECENT_CORE_TAG};
+    use crate::wallet::ListUnspentQuery;
+    use crate::{Bitcoind, BitcoindRpcApi, Client, Wallet};
     use bitcoin::util::psbt::PartiallySignedTransaction;
     use bitcoin::{Amount, Transaction, TxOut};
+    use bitcoincore_rpc_json::GetTransactionResultDetailCategory;
     use tokio::time::sleep;
 
     #[tokio::test]
@@ -193,6 +669,38 @@ mod test {
         let _res = wallet.get_wallet_transaction(txid).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn sign_message() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind = Bitcoind::new(&tc_client).unwrap();
+        bitcoind.init(5).await.unwrap();
+
+        let wallet = Wallet::new("wallet", bitcoind.node_url.clone())
+            .await
+            .unwrap();
+        let address = wallet
+            .client
+            .with_wallet(wallet.name())
+            .unwrap()
+            .getnewaddress(None, Some("legacy".into()))
+            .await
+            .unwrap();
+        let message = "Chancellor on brink of second bailout for banks";
+
+        let signature = wallet.sign_message(&address, message).await.unwrap();
+
+        let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+        let msg_hash = bitcoin::util::misc::signed_msg_hash(message);
+        assert!(signature
+            .is_signed_by_address(&secp, &address, msg_hash)
+            .unwrap());
+        assert!(wallet
+            .client
+            .verify_message(&address, &signature, message)
+            .await
+            .unwrap());
+    }
+
     #[tokio::test]
     async fn two_party_psbt_test() {
         let tc_client = testcontainers::clients::Cli::default();
@@ -308,4 +816,253 @@ mod test {
 
         let _res = wallet.transaction_block_height(txid).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn encrypted_wallet_must_be_unlocked_to_sign() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind = Bitcoind::new(&tc_client).unwrap();
+        bitcoind.init(5).await.unwrap();
+
+        let wallet = Wallet::new_encrypted("wallet", bitcoind.node_url.clone(), "passphrase")
+            .await
+            .unwrap();
+        let mint_address = wallet.new_address().await.unwrap();
+        let mint_amount = bitcoin::Amount::from_btc(3.0).unwrap();
+        bitcoind.mint(mint_address, mint_amount).await.unwrap();
+
+        let pay_address = wallet.new_address().await.unwrap();
+        let pay_amount = bitcoin::Amount::from_btc(1.0).unwrap();
+        let res = wallet
+            .send_to_address(pay_address.clone(), pay_amount)
+            .await;
+        assert!(matches!(res, Err(Error::WalletLocked)));
+
+        let res = wallet.unlock("wrong", Duration::from_secs(60)).await;
+        assert!(matches!(res, Err(Error::IncorrectPassphrase)));
+
+        let guard = wallet
+            .unlock("passphrase", Duration::from_secs(60))
+            .await
+            .unwrap();
+        let _txid = wallet
+            .send_to_address(pay_address.clone(), pay_amount)
+            .await
+            .unwrap();
+        guard.lock().await.unwrap();
+
+        let res = wallet.send_to_address(pay_address, pay_amount).await;
+        assert!(matches!(res, Err(Error::WalletLocked)));
+    }
+
+    #[tokio::test]
+    async fn backup_and_reload_wallet() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind = Bitcoind::new(&tc_client).unwrap();
+        bitcoind.init(5).await.unwrap();
+
+        let wallet = Wallet::new("wallet", bitcoind.node_url.clone())
+            .await
+            .unwrap();
+        let container_path = std::path::Path::new("/tmp/wallet.bak");
+        wallet.backup(container_path).await.unwrap();
+
+        let host_path = std::env::temp_dir().join(bitcoind.container_id());
+        bitcoind
+            .copy_from_container(container_path, &host_path)
+            .await
+            .unwrap();
+        assert!(host_path.metadata().unwrap().len() > 0);
+        std::fs::remove_file(host_path).unwrap();
+
+        wallet.unload().await.unwrap();
+        assert!(wallet.info().await.is_err());
+
+        wallet.load().await.unwrap();
+        let _info = wallet.info().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn restore_wallet_backup() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind =
+            Bitcoind::with_image(&tc_client, BitcoindImage::with_tag(RECENT_CORE_TAG)).unwrap();
+        bitcoind.init(1).await.unwrap();
+
+        let wallet = Wallet::new("wallet", bitcoind.node_url.clone())
+            .await
+            .unwrap();
+        let address = wallet.new_address().await.unwrap();
+        let backup_file = std::path::Path::new("/tmp/wallet.bak");
+        wallet.backup(backup_file).await.unwrap();
+
+        let restored = Wallet::restore("restored", bitcoind.node_url.clone(), backup_file)
+            .await
+            .unwrap();
+
+        let address_info = restored.address_info(&address).await.unwrap();
+        assert_eq!(address_info.is_mine, Some(true));
+    }
+
+    #[tokio::test]
+    async fn migrate_legacy_wallet() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind =
+            Bitcoind::with_image(&tc_client, BitcoindImage::with_tag(RECENT_CORE_TAG)).unwrap();
+        bitcoind.init(1).await.unwrap();
+        let client = Client::new(bitcoind.node_url.clone());
+        client
+            .create_wallet(
+                "legacy",
+                CreateWalletOptions {
+                    descriptors: Some(false),
+                    ..CreateWalletOptions::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let wallet = Wallet::new("legacy", bitcoind.node_url.clone())
+            .await
+            .unwrap();
+        let address = wallet.new_address().await.unwrap();
+        let migrated = wallet.migrate().await.unwrap();
+
+        assert_eq!(migrated.wallet_name, "legacy");
+        let address_info = wallet.address_info(&address).await.unwrap();
+        assert_eq!(address_info.is_mine, Some(true));
+    }
+
+    #[tokio::test]
+    async fn locked_unspent_is_not_listed() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind = Bitcoind::new(&tc_client).unwrap();
+        bitcoind.init(5).await.unwrap();
+
+        let wallet = Wallet::new("wallet", bitcoind.node_url.clone())
+            .await
+            .unwrap();
+        for btc in &[1.0, 2.0] {
+            let address = wallet.new_address().await.unwrap();
+            let amount = bitcoin::Amount::from_btc(*btc).unwrap();
+            bitcoind.mint(address, amount).await.unwrap();
+        }
+
+        let large = wallet
+            .list_unspent_with(ListUnspentQuery {
+                minimum_amount: Some(Amount::from_btc(1.5).unwrap()),
+                ..ListUnspentQuery::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(large.len(), 1);
+        assert_eq!(large[0].amount, Amount::from_btc(2.0).unwrap());
+
+        let outpoint = bitcoin::OutPoint::new(large[0].txid, large[0].vout);
+        wallet.lock_unspent(&[outpoint]).await.unwrap();
+        assert_eq!(wallet.list_locked().await.unwrap(), vec![outpoint]);
+        assert_eq!(wallet.list_unspent().await.unwrap().len(), 1);
+
+        wallet.unlock_unspent(&[outpoint]).await.unwrap();
+        assert!(wallet.list_locked().await.unwrap().is_empty());
+        assert_eq!(wallet.list_unspent().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn received_by_label() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind = Bitcoind::new(&tc_client).unwrap();
+        bitcoind.init(5).await.unwrap();
+
+        let wallet = Wallet::new("wallet", bitcoind.node_url.clone())
+            .await
+            .unwrap();
+        let alice_address = wallet.new_address_with_label("alice").await.unwrap();
+        let bob_address = wallet.new_address().await.unwrap();
+        wallet.set_label(&bob_address, "bob").await.unwrap();
+
+        let alice_amount = Amount::from_btc(1.0).unwrap();
+        let bob_amount = Amount::from_btc(2.0).unwrap();
+        bitcoind
+            .mint(alice_address.clone(), alice_amount)
+            .await
+            .unwrap();
+        bitcoind
+            .mint(bob_address.clone(), bob_amount)
+            .await
+            .unwrap();
+
+        let labels = wallet.labels().await.unwrap();
+        assert!(labels.contains(&"alice".to_string()));
+        assert!(labels.contains(&"bob".to_string()));
+        assert_eq!(
+            wallet.addresses_by_label("bob").await.unwrap(),
+            vec![bob_address.clone()]
+        );
+        assert_eq!(
+            wallet.received_by_label("alice").await.unwrap(),
+            alice_amount
+        );
+        assert_eq!(
+            wallet.received_by_address(&bob_address).await.unwrap(),
+            bob_amount
+        );
+        assert_eq!(wallet.list_received_by_address().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn list_transactions() {
+        let tc_client = testcontainers::clients::Cli::default();
+        let bitcoind = Bitcoind::new(&tc_client).unwrap();
+        bitcoind.init(5).await.unwrap();
+
+        let wallet = Wallet::new("wallet", bitcoind.node_url.clone())
+            .await
+            .unwrap();
+        let best_block_hash = wallet
+            .client
+            .getblockchaininfo()
+            .await
+            .unwrap()
+            .best_block_hash;
+        let mint_address = wallet.new_address().await.unwrap();
+        let mint_amount = Amount::from_btc(3.0).unwrap();
+        bitcoind.mint(mint_address, mint_amount).await.unwrap();
+
+        let pay_address = bitcoind_address(&bitcoind).await;
+        let pay_amount = Amount::from_btc(1.0).unwrap();
+        let txid = wallet
+            .send_to_address(pay_address, pay_amount)
+            .await
+            .unwrap();
+
+        let transactions = wallet.transactions(10, 0).await.unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(
+            transactions[0].detail.category,
+            GetTransactionResultDetailCategory::Receive
+        );
+        assert_eq!(
+            transactions[0].detail.amount,
+            mint_amount.to_signed().unwrap()
+        );
+        assert_eq!(transactions[1].info.txid, txid);
+        assert_eq!(
+            transactions[1].detail.category,
+            GetTransactionResultDetailCategory::Send
+        );
+        assert_eq!(wallet.transactions(10, 1).await.unwrap().len(), 1);
+
+        let since_block = wallet.since_block(Some(best_block_hash)).await.unwrap();
+        assert_eq!(since_block.transactions.len(), 2);
+        assert!(since_block.removed.is_empty());
+    }
+
+    async fn bitcoind_address(bitcoind: &Bitcoind<'_>) -> bitcoin::Address {
+        Wallet::new(&bitcoind.wallet_name, bitcoind.node_url.clone())
+            .await
+            .unwrap()
+            .new_address()
+            .await
+            .unwrap()
+    }
 }